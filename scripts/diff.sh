@@ -1,26 +1,18 @@
 #!/usr/bin/bash
-depth=4
+# Compares the perft divide of the move generator with stockfish
+# usage: scripts/diff.sh [depth] [fen]
+depth=${1:-4}
+fen=${2:-"r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"}
 
-fen="r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
-output=$(echo -e 'position fen '$fen'\ngo perft '$depth | stockfish)
-output=$(echo "$output" | sed '/^$/d')
-output=$(echo "$output" | sed '$d')
-output=$(echo "$output" | sort)
-#echo "$output"
+reference=$(mktemp)
+trap 'rm -f "$reference"' EXIT
 
+# castle rights with rook files need stockfish's Chess960 mode
+castling=$(echo "$fen" | cut -d ' ' -f 3)
+chess960=false
+if [[ "$castling" =~ [A-Ha-h] ]]; then chess960=true; fi
 
-output2=$(../target/release/deps/chess-dd917230f7749151)
-output2=$(echo "$output2" | sed '1,10d')
-output2=$(echo "$output2" | sed '/^$/d')
-output2=$(echo "$output2" | sed '$d')
-output2=$(echo "$output2" | sed '$d')
-output2=$(echo "$output2" | sed '$d')
-output2=$(echo "$output2" | sed '$d')
-output2=$(echo "$output2" | sed '$d')
-output2=$(echo "$output2" | sed '$d')
-output2=$(echo "$output2" | sed '$d')
-output2=$(echo "$output2" | sed '$d')
-output2=$(echo "$output2" | sort)
-#echo "$output2"
+echo -e "setoption name UCI_Chess960 value $chess960\nposition fen $fen\ngo perft $depth" | stockfish > "$reference"
 
-vimdiff <(echo "$output") <(echo "$output2")
+cd "$(dirname "$0")/.." || exit 1
+cargo run --release --quiet -- perft --compare "$reference" "$depth" "$fen"
@@ -0,0 +1,199 @@
+use std::sync::OnceLock;
+
+use crate::bitboard::bit;
+use crate::models::FigureColor;
+use crate::square::Square;
+
+// (files, ranks) steps, see `Square::offset`
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_OFFSETS: [(i8, i8); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+
+// Magic bitboard lookup for one slider on one field: the relevant blockers are multiplied
+// with the magic number and the upper bits of the product index into the attack table
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize
+}
+
+struct AttackTables {
+    knight: [u64; 64],
+    king: [u64; 64],
+    pawn: [[u64; 64]; 2],
+    rook_magics: Vec<Magic>,
+    bishop_magics: Vec<Magic>,
+    slider_attacks: Vec<u64>,
+    // indexed by 64 * from + to, empty if the two fields don't share a rank, file or diagonal
+    between: Vec<u64>,
+    line: Vec<u64>
+}
+
+fn offset_attacks(square: Square, offsets: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+    for (files, ranks) in offsets.iter() {
+        if let Some(target) = square.offset(*files, *ranks) { attacks |= bit(target); }
+    }
+    return attacks;
+}
+
+// Walks the rays until they leave the board or hit a blocker, the blocker itself is attacked
+fn ray_attacks(square: Square, occupied: u64, directions: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+    for (files, ranks) in directions.iter() {
+        let mut target = square.offset(*files, *ranks);
+        while let Some(field) = target {
+            attacks |= bit(field);
+            if occupied & bit(field) != 0 { break; }
+            target = field.offset(*files, *ranks);
+        }
+    }
+    return attacks;
+}
+
+// Blockers on the last field of a ray don't change the attacks, so they are left out of the mask
+fn relevant_mask(square: Square, directions: &[(i8, i8)]) -> u64 {
+    let mut mask = 0;
+    for (files, ranks) in directions.iter() {
+        let mut field = square;
+        while let Some(next) = field.offset(*files, *ranks) {
+            if next.offset(*files, *ranks).is_none() { break; }
+            mask |= bit(next);
+            field = next;
+        }
+    }
+    return mask;
+}
+
+// xorshift64, seeded with a constant so the magic search gives the same tables on every run
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    return *state;
+}
+
+fn find_magic(square: Square, directions: &[(i8, i8)], slider_attacks: &mut Vec<u64>, random_state: &mut u64) -> Magic {
+    let mask = relevant_mask(square, directions);
+    let bits = mask.count_ones();
+    let shift = 64 - bits;
+
+    // enumerate all blocker subsets of the mask (carry-rippler)
+    let mut occupancies = Vec::new();
+    let mut subset: u64 = 0;
+    loop {
+        occupancies.push(subset);
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 { break; }
+    }
+    let references: Vec<u64> = occupancies.iter().map(|occupied| ray_attacks(square, *occupied, directions)).collect();
+
+    let mut table = vec![0u64; 1 << bits];
+    let mut used = vec![false; 1 << bits];
+    loop {
+        let magic = next_random(random_state) & next_random(random_state) & next_random(random_state);
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 { continue; }
+
+        for entry in used.iter_mut() { *entry = false; }
+        let mut collision = false;
+        for (occupied, attacks) in occupancies.iter().zip(references.iter()) {
+            let key = (occupied.wrapping_mul(magic) >> shift) as usize;
+            if used[key] && table[key] != *attacks { collision = true; break; }
+            used[key] = true;
+            table[key] = *attacks;
+        }
+
+        if !collision {
+            let offset = slider_attacks.len();
+            slider_attacks.extend_from_slice(&table);
+            return Magic { mask: mask, magic: magic, shift: shift, offset: offset };
+        }
+    }
+}
+
+impl AttackTables {
+    fn new() -> AttackTables {
+        let mut tables = AttackTables {
+            knight: [0; 64],
+            king: [0; 64],
+            pawn: [[0; 64]; 2],
+            rook_magics: Vec::with_capacity(64),
+            bishop_magics: Vec::with_capacity(64),
+            slider_attacks: Vec::new(),
+            between: vec![0; 64 * 64],
+            line: vec![0; 64 * 64]
+        };
+
+        let mut random_state = 0x9e3779b97f4a7c15;
+        for square in Square::ALL.iter().cloned() {
+            tables.knight[square.index()] = offset_attacks(square, &KNIGHT_OFFSETS);
+            tables.king[square.index()] = offset_attacks(square, &KING_OFFSETS);
+            tables.pawn[FigureColor::WHITE as usize][square.index()] = offset_attacks(square, &[(-1, 1), (1, 1)]);
+            tables.pawn[FigureColor::BLACK as usize][square.index()] = offset_attacks(square, &[(-1, -1), (1, -1)]);
+            tables.rook_magics.push(find_magic(square, &ROOK_DIRECTIONS, &mut tables.slider_attacks, &mut random_state));
+            tables.bishop_magics.push(find_magic(square, &BISHOP_DIRECTIONS, &mut tables.slider_attacks, &mut random_state));
+        }
+
+        for from in Square::ALL.iter().cloned() {
+            for to in Square::ALL.iter().cloned() {
+                for directions in [&ROOK_DIRECTIONS, &BISHOP_DIRECTIONS].iter() {
+                    if from == to || ray_attacks(from, 0, *directions) & bit(to) == 0 { continue; }
+                    let key = 64 * from.index() + to.index();
+                    tables.between[key] = ray_attacks(from, bit(to), *directions) & ray_attacks(to, bit(from), *directions);
+                    tables.line[key] = (ray_attacks(from, 0, *directions) & ray_attacks(to, 0, *directions)) | bit(from) | bit(to);
+                }
+            }
+        }
+
+        return tables;
+    }
+
+    fn slider_lookup(&self, magic: &Magic, occupied: u64) -> u64 {
+        let key = ((occupied & magic.mask).wrapping_mul(magic.magic) >> magic.shift) as usize;
+        self.slider_attacks[magic.offset + key]
+    }
+}
+
+fn tables() -> &'static AttackTables {
+    static TABLES: OnceLock<AttackTables> = OnceLock::new();
+    TABLES.get_or_init(AttackTables::new)
+}
+
+pub fn knight_attacks(square: Square) -> u64 {
+    tables().knight[square.index()]
+}
+
+pub fn king_attacks(square: Square) -> u64 {
+    tables().king[square.index()]
+}
+
+// Fields a pawn of `figure_color` on `square` attacks
+pub fn pawn_attacks(figure_color: FigureColor, square: Square) -> u64 {
+    tables().pawn[figure_color as usize][square.index()]
+}
+
+pub fn rook_attacks(square: Square, occupied: u64) -> u64 {
+    let tables = tables();
+    tables.slider_lookup(&tables.rook_magics[square.index()], occupied)
+}
+
+pub fn bishop_attacks(square: Square, occupied: u64) -> u64 {
+    let tables = tables();
+    tables.slider_lookup(&tables.bishop_magics[square.index()], occupied)
+}
+
+pub fn queen_attacks(square: Square, occupied: u64) -> u64 {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+// Fields strictly between `from` and `to` if they share a rank, file or diagonal
+pub fn between(from: Square, to: Square) -> u64 {
+    tables().between[64 * from.index() + to.index()]
+}
+
+// The whole rank, file or diagonal through `from` and `to`, empty if there is none
+pub fn line(from: Square, to: Square) -> u64 {
+    tables().line[64 * from.index() + to.index()]
+}
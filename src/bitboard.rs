@@ -0,0 +1,21 @@
+use crate::square::Square;
+
+// Bit `i` of a bitboard stands for the field with index `i`, so bit 0 is a8 and bit 63 is h1
+
+pub fn bit(square: Square) -> u64 {
+    1u64 << square.index()
+}
+
+// Iterates over the fields of all set bits, lowest index first
+pub struct BitIter(pub u64);
+
+impl Iterator for BitIter {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == 0 { return None; }
+        let square = Square::ALL[self.0.trailing_zeros() as usize];
+        self.0 &= self.0 - 1;
+        return Some(square);
+    }
+}
@@ -1,244 +1,342 @@
 use std::vec::Vec;
 use std::collections::HashMap;
-use std::collections::hash_map::DefaultHasher;
-use std::time::SystemTime;
-use std::hash::{Hash, Hasher};
 
 
-use crate::models::{FigureType, FigureColor, Field, Board, PositionDescription};
+use crate::attacks::{knight_attacks, king_attacks, pawn_attacks, rook_attacks, bishop_attacks, queen_attacks, between, line};
+use crate::bitboard::{bit, BitIter};
+use crate::zobrist::{castle_key, en_passant_key, KEYS, TURN_OFFSET};
+use crate::models::{FigureType, FigureColor, Field, Board, Move, MoveList, Undo, PositionDescription, GameStatus};
+use crate::square::{File, Rank, Square};
 
-fn is_occupied(x: i8, y: i8, board: &Board) -> FigureColor {
-    board.fields[(x + y*8) as usize].figure_color
+fn opponent(figure_color: FigureColor) -> FigureColor {
+    if figure_color == FigureColor::WHITE { FigureColor::BLACK } else { FigureColor::WHITE }
 }
 
-pub fn is_checked(field_index: i8, figure_color: FigureColor, board: Board) -> bool {
-    let mut board_cpy = board.clone();
-    let check_field = board_cpy.fields[field_index as usize];
+// Ranks a pawn of `figure_color` moves per step, white pawns move up towards rank 8
+fn pawn_direction(figure_color: FigureColor) -> i8 {
+    if figure_color == FigureColor::WHITE { 1 } else { -1 }
+}
+
+// Pieces of `by_color` attacking `square`, with sliders seeing through everything not in `occupied`
+pub fn attackers(square: Square, by_color: FigureColor, occupied: u64, board: &Board) -> u64 {
+    let queens = board.pieces_of(FigureType::QUEEN, by_color);
+
+    // a field is attacked by a piece if the same piece placed on the field could capture it
+    return pawn_attacks(opponent(by_color), square) & board.pieces_of(FigureType::PAWN, by_color)
+        | knight_attacks(square) & board.pieces_of(FigureType::KNIGHT, by_color)
+        | king_attacks(square) & board.pieces_of(FigureType::KING, by_color)
+        | bishop_attacks(square, occupied) & (board.pieces_of(FigureType::BISHOP, by_color) | queens)
+        | rook_attacks(square, occupied) & (board.pieces_of(FigureType::ROOK, by_color) | queens);
+}
+
+pub fn is_square_attacked(square: Square, by_color: FigureColor, board: &Board) -> bool {
+    attackers(square, by_color, board.occupied(), board) != 0
+}
 
-    if check_field.figure_type == FigureType::NONE {
-        board_cpy.fields[field_index as usize] = Field { figure_type: FigureType::NONE, figure_color: figure_color };
+// Whether a piece of `figure_color` on `square` would be attacked by the opponent
+pub fn is_checked(square: Square, figure_color: FigureColor, board: &Board) -> bool {
+    is_square_attacked(square, opponent(figure_color), board)
+}
+
+pub fn is_king_checked(figure_color: FigureColor, board: &Board) -> bool {
+    match board.king_square(figure_color) {
+        Some(king) => is_checked(king, figure_color, board),
+        None => false
     }
+}
 
-    for i in 0..64 {
-        let field = board_cpy.fields[i as usize];
-        if field.figure_color != FigureColor::NONE && field.figure_color != figure_color {
-            let reachable_fields = calc_reachable_fields(i, &board_cpy, false);
-            let checking_field: Vec<&(i8, FigureType)> = reachable_fields.iter().filter(|field| field.0 == field_index).collect();
-            if checking_field.len() > 0 { return true; }
+// Attackers of every field by both colors in one position. Defenders count as attackers of the
+// field they stand on and x-rays through other pieces are left out, `attackers` with a reduced
+// occupancy covers those.
+#[derive(Clone, Copy)]
+pub struct AttackMap {
+    attackers: [[u64; 64]; 2],
+    pieces: [u64; 6]
+}
+
+impl AttackMap {
+    pub fn new(board: &Board) -> AttackMap {
+        let occupied = board.occupied();
+        let mut map = AttackMap { attackers: [[0; 64]; 2], pieces: board.pieces };
+        for color in [FigureColor::WHITE, FigureColor::BLACK].iter() {
+            for square in Square::ALL.iter().cloned() {
+                map.attackers[*color as usize][square.index()] = attackers(square, *color, occupied, board);
+            }
         }
+        return map;
+    }
+
+    pub fn attackers(&self, square: Square, by_color: FigureColor) -> u64 {
+        self.attackers[by_color as usize][square.index()]
+    }
+
+    pub fn count(&self, square: Square, by_color: FigureColor) -> u32 {
+        self.attackers(square, by_color).count_ones()
+    }
+
+    // The attacker that is cheapest to give up, in the order pawn, knight, bishop, rook, queen, king
+    pub fn lowest_attacker(&self, square: Square, by_color: FigureColor) -> Option<Square> {
+        let attackers = self.attackers(square, by_color);
+        [FigureType::PAWN, FigureType::KNIGHT, FigureType::BISHOP, FigureType::ROOK, FigureType::QUEEN, FigureType::KING].iter()
+            .find_map(|figure_type| BitIter(attackers & self.pieces[*figure_type as usize]).next())
+    }
+
+    // All fields `by_color` attacks at least once
+    pub fn attacked(&self, by_color: FigureColor) -> u64 {
+        Square::ALL.iter().filter(|square| self.attackers(**square, by_color) != 0).fold(0, |attacked, square| attacked | bit(*square))
     }
-    return false;
 }
 
-pub fn is_king_checked(figure_color: FigureColor, board: Board) -> bool {
-    let mut king_field_index = 0;
-    for i in 0..64 {
-        let field = board.fields[i as usize];
-        if field.figure_color == figure_color && field.figure_type == FigureType::KING {
-            king_field_index = i; 
-        }
+fn push_move(vec: &mut MoveList, src_field: Square, target_field: Square, promotion: FigureType, flags: u8, board: &Board) {
+    let mut flags = flags;
+    if board.fields[target_field.index()].figure_color != FigureColor::NONE { flags |= Move::CAPTURE; }
+    vec.push(Move { from: src_field, to: target_field, promotion: promotion, flags: flags });
+}
+
+fn push_pawn_move(vec: &mut MoveList, src_field: Square, target_field: Square, flags: u8, board: &Board) {
+    if target_field.rank() == Rank::FIRST || target_field.rank() == Rank::EIGHTH {
+        push_move(vec, src_field, target_field, FigureType::QUEEN, flags, board);
+        push_move(vec, src_field, target_field, FigureType::KNIGHT, flags, board);
+        push_move(vec, src_field, target_field, FigureType::BISHOP, flags, board);
+        push_move(vec, src_field, target_field, FigureType::ROOK, flags, board);
+    } else {
+        push_move(vec, src_field, target_field, FigureType::NONE, flags, board);
     }
-    return is_checked(king_field_index, board.fields[king_field_index as usize].figure_color, board);
 }
 
-pub fn calc_reachable_fields(src_field: i8, board: &Board, check: bool) -> Vec<(i8, FigureType)> {
-    let mut vec = Vec::new();
+pub fn calc_legal_moves(src_field: Square, board: &Board) -> Vec<Move> {
+    generate_legal_moves(board).iter().filter(|m| m.from == src_field).cloned().collect()
+}
 
-    let field = &board.fields[src_field as usize];
+#[derive(Clone, Copy, PartialEq)]
+enum MoveFilter {
+    All,
+    Captures,
+    Quiets
+}
 
-    let pos = (src_field % 8, src_field / 8);
-    let (x, y) = pos; 
+// Own pieces that are the only piece between their king and an enemy slider
+fn pinned_pieces(king_field: Square, figure_color: FigureColor, board: &Board) -> u64 {
+    let enemy = opponent(figure_color);
+    let enemies = board.colors[enemy as usize];
+    let queens = board.pieces_of(FigureType::QUEEN, enemy);
 
-    match field.figure_type {
-        FigureType::KING => {
-            for dx in -1..2 {
-                for dy in -1..2 {
-                    if dx == 0 && dy == 0 { continue; }
+    // sliders that would attack the king if only enemy pieces stood in the way
+    let snipers = rook_attacks(king_field, enemies) & (board.pieces_of(FigureType::ROOK, enemy) | queens)
+        | bishop_attacks(king_field, enemies) & (board.pieces_of(FigureType::BISHOP, enemy) | queens);
 
-                    if dx + x >= 0 && dx + x < 8 && dy + y >= 0 && dy + y < 8 {
-                        if is_occupied(dx + x, dy + y, board) == field.figure_color { continue; }
-                        vec.push((dx + x + ((dy + y) * 8), FigureType::NONE));
-                    }
-                }
-            }
+    let mut pinned = 0;
+    for sniper in BitIter(snipers) {
+        let blockers = between(king_field, sniper) & board.occupied();
+        if blockers.count_ones() == 1 { pinned |= blockers & board.colors[figure_color as usize]; }
+    }
+    return pinned;
+}
 
-            // Castle
-            let i = if field.figure_color == FigureColor::BLACK { 0 } else { 1 };
-            if src_field != 4+i*56 { return vec; }
-            for corner_index in [i*56, i*56+7].iter() {
-                let corner_field = board.fields[*corner_index as usize];
-                if corner_field.figure_type != FigureType::ROOK || corner_field.figure_color != board.fields[src_field as usize].figure_color { continue; }
-                if corner_index % 8 == 0 {
-                    // queen-side 
-                    if !((field.figure_color == FigureColor::BLACK && board.castle_rights.q) || (field.figure_color == FigureColor::WHITE && board.castle_rights.Q)) { continue; }
-                } else  {
-                    // king-side
-                    if !((field.figure_color == FigureColor::BLACK && board.castle_rights.k) || (field.figure_color == FigureColor::WHITE && board.castle_rights.K)) { continue; }
-                }
-
-                let dx_range = if corner_index % 8 == 0 { vec![-1,-2,-3] } else { vec![1,2] };
-
-                let mut obstacle = false;
-                for dx in dx_range { 
-                    if is_occupied(x+dx, y, board) != FigureColor::NONE { obstacle = true; break; } 
-                }
-
-                if !obstacle {
-                    let dx_range = if corner_index % 8 == 0 { vec![0,-1,-2] } else { vec![0,1,2] };
-                    for dx in dx_range {
-                        if check && is_checked(dx + x + y*8, field.figure_color, *board) { obstacle = true; break; }
-                    }
-                }
-
-                if !obstacle { vec.push((*corner_index, FigureType::NONE)); }
-            }
-        },
-        FigureType::PAWN => {
-            let dy = if field.figure_color == FigureColor::WHITE { 1 } else { -1 };
-            if is_occupied(x, y-dy, board) == FigureColor::NONE {
-                if y-dy == 0 || y-dy == 7 {
-                    vec.push((x + (y-dy)*8, FigureType::QUEEN));
-                    vec.push((x + (y-dy)*8, FigureType::KNIGHT));
-                    vec.push((x + (y-dy)*8, FigureType::BISHOP));
-                    vec.push((x + (y-dy)*8, FigureType::ROOK));
-                } else {
-                    vec.push((x + (y-dy)*8, FigureType::NONE));
-                }
-
-                if (y+dy == 0 || y+dy == 7) && is_occupied(x, y-dy*2, board) == FigureColor::NONE {
-                    vec.push((x + (y-dy*2)*8, FigureType::NONE));
-                }
-            }
-            for dx in [-1, 1].iter() {
-                if x+dx >= 0 && x+dx < 8 {
-                    let occupation = is_occupied(x+dx, y-dy, board);
-                    if (occupation != FigureColor::NONE || (x+dx + (y-dy)*8) == board.en_passant) && occupation != field.figure_color {
-                        if y-dy == 0 || y-dy == 7 {
-                            vec.push((x+dx + (y-dy)*8, FigureType::QUEEN));
-                            vec.push((x+dx + (y-dy)*8, FigureType::KNIGHT));
-                            vec.push((x+dx + (y-dy)*8, FigureType::BISHOP));
-                            vec.push((x+dx + (y-dy)*8, FigureType::ROOK));
-                        } else {
-                            vec.push((x+dx + (y-dy)*8, FigureType::NONE));
-                        }
-                    }
-                }
-            }
-        },
-        FigureType::KNIGHT => {
-            for dx in -2..3i8 {
-                for dy in -2..3i8 {
-                    if dy.abs() + dx.abs() == 3 {
-                        if dx + x >= 0 && dx + x < 8 && dy + y >= 0 && dy + y < 8 {
-                            if is_occupied(dx + x, dy + y, board) == field.figure_color { continue; }
-                            vec.push((dx + x + ((dy + y) * 8), FigureType::NONE));
-                        }
-                    }
-                }
+fn push_pawn_moves(vec: &mut MoveList, src_field: Square, allowed: u64, filter: MoveFilter, king_field: Square, board: &Board) {
+    let figure_color = board.active;
+    let enemy = opponent(figure_color);
+    let occupied = board.occupied();
+    let forward = pawn_direction(figure_color);
+
+    if let Some(single_push) = src_field.offset(0, forward).filter(|push| occupied & bit(*push) == 0) {
+        if allowed & bit(single_push) != 0 { push_pawn_move(vec, src_field, single_push, 0, board); }
+
+        let start_rank = if figure_color == FigureColor::WHITE { Rank::SECOND } else { Rank::SEVENTH };
+        if let Some(double_push) = single_push.offset(0, forward).filter(|_| src_field.rank() == start_rank) {
+            if occupied & bit(double_push) == 0 && allowed & bit(double_push) != 0 {
+                push_move(vec, src_field, double_push, FigureType::NONE, Move::DOUBLE_PUSH, board);
             }
         }
-        FigureType::ROOK => {
-            for dx in -1..2 {
-                for dy in -1..2 {
-                    if (dy != 0 && dx != 0) || (dx == 0 && dy == 0 ) { continue; }
-                    for i in 1..8 {
-                        if dx*i + x >= 0 && dx*i + x < 8 && dy*i + y >= 0 && dy*i + y < 8 {
-                            if is_occupied(dx*i + x, dy*i + y, board) == field.figure_color { break; }
-                            if is_occupied(dx*i + x, dy*i + y, board) != FigureColor::NONE { 
-                                vec.push((dx*i + x + ((dy*i + y) * 8), FigureType::NONE));
-                                break;
-                            }
-                            vec.push((dx*i + x + ((dy*i + y) * 8), FigureType::NONE));
-                        }
-                    }
-                }
-            }            
+    }
+
+    let attacks = pawn_attacks(figure_color, src_field);
+    for target_field in BitIter(attacks & board.colors[enemy as usize] & allowed) {
+        push_pawn_move(vec, src_field, target_field, 0, board);
+    }
+
+    // En passant removes two pieces from the rank of the king, so pins and checks are tested
+    // directly on the occupancy after the capture instead of going through `allowed`
+    if let Some(en_passant) = board.en_passant.filter(|en_passant| filter != MoveFilter::Quiets && attacks & bit(*en_passant) != 0) {
+        // the captured pawn stands next to the capturing one
+        let captured_field = Square::from_coords(en_passant.file(), src_field.rank());
+        let occupied_after = (occupied ^ bit(src_field) ^ bit(captured_field)) | bit(en_passant);
+        if attackers(king_field, enemy, occupied_after, board) & !bit(captured_field) == 0 {
+            push_move(vec, src_field, en_passant, FigureType::NONE, Move::CAPTURE | Move::EN_PASSANT, board);
         }
-        FigureType::BISHOP => {
-            for dx in -1..2 {
-                for dy in -1..2 {
-                    if !(dy != 0 && dx != 0) || (dx == 0 && dy == 0 ) { continue; }
-                    for i in 1..8 {
-                        if dx*i + x >= 0 && dx*i + x < 8 && dy*i + y >= 0 && dy*i + y < 8 {
-                            if is_occupied(dx*i + x, dy*i + y, board) == field.figure_color { break; }
-                            if is_occupied(dx*i + x, dy*i + y, board) != FigureColor::NONE { 
-                                vec.push((dx*i + x + ((dy*i + y) * 8), FigureType::NONE));
-                                break;
-                            }
-                            vec.push((dx*i + x + ((dy*i + y) * 8), FigureType::NONE));
-                        }
-                    }
-                }
-            }            
+    }
+}
+
+// Castling in standard chess and Chess960: the king ends on the g or c file and the rook next to it
+// on the f or d file. All fields both pieces cross have to be empty apart from the two of them
+// and the king may not pass an attacked field, being in check is ruled out by the caller.
+fn push_castle_moves(vec: &mut MoveList, king_field: Square, board: &Board) {
+    let figure_color = board.active;
+    let back_rank = if figure_color == FigureColor::BLACK { Rank::EIGHTH } else { Rank::FIRST };
+    if king_field.rank() != back_rank { return; }
+
+    let (king_side, queen_side) = if figure_color == FigureColor::WHITE {
+        (board.castle_rights.K, board.castle_rights.Q)
+    } else {
+        (board.castle_rights.k, board.castle_rights.q)
+    };
+    let rooks = board.pieces_of(FigureType::ROOK, figure_color);
+    let occupied = board.occupied();
+    let sides = [
+        (king_side, board.castle_rights.king_side_file[figure_color as usize], File::G, File::F),
+        (queen_side, board.castle_rights.queen_side_file[figure_color as usize], File::C, File::D)
+    ];
+
+    for (castle_right, rook_file, king_file, rook_target_file) in sides.iter() {
+        let rook_field = Square::from_coords(*rook_file, back_rank);
+        if !castle_right || rooks & bit(rook_field) == 0 { continue; }
+
+        let king_target = Square::from_coords(*king_file, back_rank);
+        let rook_target = Square::from_coords(*rook_target_file, back_rank);
+        let king_path = between(king_field, king_target) | bit(king_target);
+        let rook_path = between(rook_field, rook_target) | bit(rook_target);
+        let castling_pieces = bit(king_field) | bit(rook_field);
+        if (king_path | rook_path) & occupied & !castling_pieces != 0 { continue; }
+
+        // the rook leaves the rank first, it may have been shielding the king's path
+        let occupied_after = occupied & !castling_pieces;
+        if BitIter(king_path & !bit(king_field)).any(|field| attackers(field, opponent(figure_color), occupied_after, board) != 0) { continue; }
+
+        // castle moves target the rook, see `Move`
+        vec.push(Move { from: king_field, to: rook_field, promotion: FigureType::NONE, flags: Move::CASTLE });
+    }
+}
+
+// Generates only legal moves: checkers and pinned pieces are computed once, so no move has to be played to test it
+fn generate_moves(board: &Board, filter: MoveFilter) -> MoveList {
+    let mut list = MoveList::new();
+
+    let figure_color = board.active;
+    let enemy = opponent(figure_color);
+    let own = board.colors[figure_color as usize];
+    let enemies = board.colors[enemy as usize];
+    let occupied = own | enemies;
+    let king_field = match board.king_square(figure_color) {
+        Some(king_field) => king_field,
+        None => return list
+    };
+
+    let filter_mask = match filter {
+        MoveFilter::All => !own,
+        MoveFilter::Captures => enemies,
+        MoveFilter::Quiets => !occupied
+    };
+
+    // the king must not stay on the ray of a checking slider, so it is left out of the occupancy
+    let without_king = occupied ^ bit(king_field);
+    for target_field in BitIter(king_attacks(king_field) & filter_mask) {
+        if attackers(target_field, enemy, without_king, board) == 0 {
+            push_move(&mut list, king_field, target_field, FigureType::NONE, 0, board);
         }
-        FigureType::QUEEN => {
-            for dx in -1..2 {
-                for dy in -1..2 {
-                    if dx == 0 && dy == 0 { continue; }
-                    for i in 1..8 {
-                        if dx*i + x >= 0 && dx*i + x < 8 && dy*i + y >= 0 && dy*i + y < 8 {
-                            if is_occupied(dx*i + x, dy*i + y, board) == field.figure_color { break; }
-                            if is_occupied(dx*i + x, dy*i + y, board) != FigureColor::NONE { 
-                                vec.push((dx*i + x + ((dy*i + y) * 8), FigureType::NONE));
-                                break;
-                            }
-                            vec.push((dx*i + x + ((dy*i + y) * 8), FigureType::NONE));
-                        }
-                    }
-                }
-            }            
+    }
+
+    // in double check only the king can move
+    let checkers = attackers(king_field, enemy, occupied, board);
+    if checkers.count_ones() > 1 { return list; }
+
+    // a single check has to be resolved by capturing the checker or blocking its ray
+    let check_mask = match BitIter(checkers).next() {
+        Some(checker) => checkers | between(king_field, checker),
+        None => !0
+    };
+    let pinned = pinned_pieces(king_field, figure_color, board);
+
+    for src_field in BitIter(own & !bit(king_field)) {
+        let mut allowed = check_mask & filter_mask;
+        // pinned pieces can only move along the pin
+        if pinned & bit(src_field) != 0 { allowed &= line(king_field, src_field); }
+
+        let targets = match board.fields[src_field.index()].figure_type {
+            FigureType::KNIGHT => knight_attacks(src_field),
+            FigureType::BISHOP => bishop_attacks(src_field, occupied),
+            FigureType::ROOK => rook_attacks(src_field, occupied),
+            FigureType::QUEEN => queen_attacks(src_field, occupied),
+            FigureType::PAWN => { push_pawn_moves(&mut list, src_field, allowed, filter, king_field, board); 0 },
+            _ => 0
+        };
+        for target_field in BitIter(targets & allowed) {
+            push_move(&mut list, src_field, target_field, FigureType::NONE, 0, board);
         }
-        _ => vec.push((-1, FigureType::NONE))
     }
 
-    vec
+    if checkers == 0 && filter != MoveFilter::Captures { push_castle_moves(&mut list, king_field, board); }
+
+    return list;
+}
+// All legal moves of the side to move
+pub fn generate_legal_moves(board: &Board) -> MoveList {
+    generate_moves(board, MoveFilter::All)
 }
 
-fn is_legal(src_field_id: i8, target_move: (i8, FigureType), board: Board) -> bool {
-    let mut new_board = board.clone();
-    let src_field = board.fields[src_field_id as usize];
-    play_move(src_field_id, target_move, &mut new_board);
-    return !is_king_checked(src_field.figure_color, new_board);
+// Legal captures of the side to move, en passant and capturing promotions included
+pub fn generate_captures(board: &Board) -> MoveList {
+    generate_moves(board, MoveFilter::Captures)
 }
 
-pub fn calc_legal_moves(src_field: i8, board: &Board) -> Vec<(i8, FigureType)> {
-    let mut reachable_fields = calc_reachable_fields(src_field, board, true);
+// Legal moves of the side to move that don't capture, including castling and non-capturing promotions
+pub fn generate_quiet_moves(board: &Board) -> MoveList {
+    generate_moves(board, MoveFilter::Quiets)
+}
 
-    reachable_fields = reachable_fields.into_iter().filter(|field| is_legal(src_field, *field, *board)).collect();
+const EMPTY_FIELD: Field = Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE };
 
-    return reachable_fields;
+// Called for fields a rook leaves or a piece is captured on
+fn remove_castle_right(square: Square, board: &mut Board) {
+    let rights = &mut board.castle_rights;
+    let (black, white) = (FigureColor::BLACK as usize, FigureColor::WHITE as usize);
+    if square == Square::from_coords(rights.queen_side_file[black], Rank::EIGHTH) { rights.q = false; }
+    if square == Square::from_coords(rights.king_side_file[black], Rank::EIGHTH) { rights.k = false; }
+    if square == Square::from_coords(rights.queen_side_file[white], Rank::FIRST) { rights.Q = false; }
+    if square == Square::from_coords(rights.king_side_file[white], Rank::FIRST) { rights.K = false; }
 }
 
-pub fn play_move (source_field_index: i8, target_move: (i8, FigureType), board: &mut Board) {
-    let target_field_index = target_move.0;
-    let source_field = board.fields[source_field_index as usize];
-    let target_field = board.fields[target_field_index as usize];
+// Fields the king and the rook end up on when castling with `m`
+fn castle_targets(m: Move) -> (Square, Square) {
+    let (king_file, rook_file) = if m.to.file() > m.from.file() { (File::G, File::F) } else { (File::C, File::D) };
+    (Square::from_coords(king_file, m.from.rank()), Square::from_coords(rook_file, m.from.rank()))
+}
 
-    // en-passant
-    if source_field.figure_type == FigureType::PAWN {
-        if (source_field_index - target_field_index).abs() == 16 {
-            board.en_passant = (target_field_index - source_field_index) / 2 + source_field_index;
-        } else {
-            board.en_passant = -1;
-            if (source_field_index - target_field_index).abs() != 8 && target_field.figure_type == FigureType::NONE {
-                if source_field.figure_color == FigureColor::WHITE {
-                    board.fields[(target_field_index+8) as usize] = Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE };
-                } else {
-                    board.fields[(target_field_index-8) as usize] = Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE };
-                }
-            }
-        }
-    } else { board.en_passant = -1; }
+// The field of the pawn taken by an en passant capture, next to the capturing pawn
+fn en_passant_capture(m: Move) -> Square {
+    Square::from_coords(m.to.file(), m.from.rank())
+}
 
-    if source_field.figure_type == FigureType::ROOK {
-        if source_field_index == 0 { board.castle_rights.q = false; }
-        if source_field_index == 7 { board.castle_rights.k = false; }
-        if source_field_index == 56 { board.castle_rights.Q = false; }
-        if source_field_index == 63 { board.castle_rights.K = false; }
+pub fn play_move(m: Move, board: &mut Board) -> Undo {
+    let source_field = board.fields[m.from.index()];
+    let captured_square = if m.is_en_passant() { en_passant_capture(m) } else { m.to };
+    let undo = Undo {
+        captured: if m.is_capture() { board.fields[captured_square.index()] } else { EMPTY_FIELD },
+        castle_rights: board.castle_rights,
+        en_passant: board.en_passant,
+        halfmove_clock: board.halfmove_clock,
+        fullmove_number: board.fullmove_number,
+        hash: board.hash
+    };
+
+    // the pieces are hashed by `set_field`, castle rights and en passant are rehashed once the move is done
+    board.hash ^= castle_key(board) ^ en_passant_key(board);
+
+    // move counters
+    if source_field.figure_type == FigureType::PAWN || m.is_capture() {
+        board.halfmove_clock = 0;
+    } else {
+        board.halfmove_clock = board.halfmove_clock.saturating_add(1);
     }
+    if board.active == FigureColor::BLACK { board.fullmove_number = board.fullmove_number.saturating_add(1); }
 
-    if source_field.figure_type == FigureType::KING && target_field.figure_type != FigureType::ROOK && source_field.figure_color != target_field.figure_color {
-        // non-castle king move
+    // en-passant
+    board.en_passant = if m.is_double_push() { m.from.offset(0, pawn_direction(source_field.figure_color)) } else { None };
+    if m.is_en_passant() { board.set_field(captured_square, EMPTY_FIELD); }
+
+    // castle rights are lost once the king or a rook leaves its square or a rook is captured
+    if source_field.figure_type == FigureType::ROOK { remove_castle_right(m.from, board); }
+    if m.is_capture() { remove_castle_right(m.to, board); }
+    if source_field.figure_type == FigureType::KING {
         if source_field.figure_color == FigureColor::WHITE { 
             board.castle_rights.Q = false;
             board.castle_rights.K = false;
@@ -246,153 +344,204 @@ pub fn play_move (source_field_index: i8, target_move: (i8, FigureType), board:
             board.castle_rights.q = false;
             board.castle_rights.k = false;
         }
+    }
 
-        board.fields[target_field_index as usize] = board.fields[source_field_index as usize];
-        board.fields[source_field_index as usize] = Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE }; 
-    } else if source_field.figure_type == FigureType::KING && target_field.figure_type == FigureType::ROOK && source_field.figure_color == target_field.figure_color {
-        // Castle
+    if m.is_castle() {
+        // the targets may be the fields the king and rook start from, so both are cleared first
+        let (king_target, rook_target) = castle_targets(m);
+        board.set_field(m.from, EMPTY_FIELD);
+        board.set_field(m.to, EMPTY_FIELD);
+        board.set_field(rook_target, Field { figure_type: FigureType::ROOK, figure_color: source_field.figure_color });
+        board.set_field(king_target, Field { figure_type: FigureType::KING, figure_color: source_field.figure_color });
+    } else if m.promotion != FigureType::NONE {
+        board.set_field(m.to, Field { figure_type: m.promotion, figure_color: source_field.figure_color });
+        board.set_field(m.from, EMPTY_FIELD);
+    } else {
+        board.set_field(m.to, source_field);
+        board.set_field(m.from, EMPTY_FIELD);
+    }
 
-        let side = if target_field_index % 8 != 0 { 1 } else { -1 };
-        board.fields[source_field_index as usize] = Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE }; 
-        board.fields[target_field_index as usize] = Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE }; 
-        board.fields[(source_field_index + 1*side) as usize] = Field { figure_type: FigureType::ROOK, figure_color: source_field.figure_color }; 
-        board.fields[(source_field_index + 2*side) as usize] = Field { figure_type: FigureType::KING, figure_color: source_field.figure_color }; 
+    board.active = opponent(board.active);
+    board.hash ^= castle_key(board) ^ en_passant_key(board) ^ KEYS[TURN_OFFSET];
 
-        if side == 1 {
-            if board.active == FigureColor::WHITE { board.castle_rights.K = false; } else { board.castle_rights.k = false; }
-        } else {
-            if board.active == FigureColor::WHITE { board.castle_rights.Q = false; } else { board.castle_rights.q = false; }
-        }
-    } else if source_field.figure_type == FigureType::PAWN && (target_field_index < 8 || target_field_index > 56) {
-        // Promotion
+    return undo;
+}
 
-        board.fields[target_field_index as usize] = Field { figure_type: target_move.1, figure_color: source_field.figure_color };
-        board.fields[source_field_index as usize] = Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE }; 
+pub fn unmake_move(m: Move, undo: Undo, board: &mut Board) {
+    board.active = opponent(board.active);
+
+    if m.is_castle() {
+        let (king_target, rook_target) = castle_targets(m);
+        let king_field = board.fields[king_target.index()];
+        let rook_field = board.fields[rook_target.index()];
+        board.set_field(rook_target, EMPTY_FIELD);
+        board.set_field(king_target, EMPTY_FIELD);
+        board.set_field(m.from, king_field);
+        board.set_field(m.to, rook_field);
     } else {
-        board.fields[target_field_index as usize] = board.fields[source_field_index as usize];
-        board.fields[source_field_index as usize] = Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE }; 
+        let moved_field = board.fields[m.to.index()];
+        let source_field = if m.promotion != FigureType::NONE { Field { figure_type: FigureType::PAWN, figure_color: moved_field.figure_color } } else { moved_field };
+        board.set_field(m.from, source_field);
+        if m.is_en_passant() {
+            board.set_field(m.to, EMPTY_FIELD);
+            board.set_field(en_passant_capture(m), undo.captured);
+        } else {
+            board.set_field(m.to, undo.captured);
+        }
     }
 
-    board.active = if board.active == FigureColor::WHITE { FigureColor::BLACK } else { FigureColor::WHITE }; 
+    board.castle_rights = undo.castle_rights;
+    board.en_passant = undo.en_passant;
+    board.halfmove_clock = undo.halfmove_clock;
+    board.fullmove_number = undo.fullmove_number;
+    board.hash = undo.hash;
 }
 
 fn evaluate_position(board: &Board) -> i32 {
+    if board.is_insufficient_material() { return 0; }
+
     let mut score = 0;
 
-    for field in board.fields.iter() {
-        let side_multiplier = if field.figure_color == board.active { 1 } else { -1 };
-        match field.figure_type {
-            FigureType::PAWN => score += side_multiplier * 100,
-            FigureType::KNIGHT => score += side_multiplier * 100 * 3,
-            FigureType::BISHOP => score += side_multiplier * 100 * 3,
-            FigureType::ROOK => score += side_multiplier * 100 * 5,
-            FigureType::QUEEN => score += side_multiplier * 100 * 9,
-            FigureType::KING => score += side_multiplier * 100 * 200,
-            _ => ()
-        }
+    let figure_values = [
+        (FigureType::PAWN, 100),
+        (FigureType::KNIGHT, 100 * 3),
+        (FigureType::BISHOP, 100 * 3),
+        (FigureType::ROOK, 100 * 5),
+        (FigureType::QUEEN, 100 * 9),
+        (FigureType::KING, 100 * 200),
+    ];
+    for (figure_type, value) in figure_values.iter() {
+        let own = board.pieces_of(*figure_type, board.active).count_ones() as i32;
+        let other = board.pieces_of(*figure_type, opponent(board.active)).count_ones() as i32;
+        score += (own - other) * value;
     }
 
     return score;
 }
 
-pub fn nega_max(board: &Board, depth: u8, best_move: &mut (i8, (i8, FigureType))) -> i32 {
+pub fn nega_max(board: &Board, depth: u8, best_move: &mut Option<Move>) -> i32 {
     if depth == 0 { return evaluate_position(board); }
 
     let mut max = -32767;
-    let mut can_move = false;
-
-    for index in 0..64i8 {
-        let field = board.fields[index as usize];
-        if field.figure_color == board.active {
-            let legal_moves = calc_reachable_fields(index as i8, &board, true);
-            if legal_moves.len() > 0 { can_move = true; }
-            for m in legal_moves {
-                let mut board_cpy = board.clone();
-                play_move(index as i8, m, &mut board_cpy);
-                if !is_king_checked(board.active, board_cpy) {
-                    let score = -nega_max(&board_cpy, depth-1, best_move);
-
-                    if score > max {
-                        max = score;
-                        if depth == 4 {
-                            *best_move = (index, m);
-                        }
-                    }
-                }
+
+    for m in generate_legal_moves(board).iter() {
+        let mut board_cpy = *board;
+        play_move(*m, &mut board_cpy);
+        let score = -nega_max(&board_cpy, depth-1, best_move);
+
+        if score > max {
+            max = score;
+            if depth == 4 {
+                *best_move = Some(*m);
             }
         }
     }
 
-    if !can_move { println!("can't move anymore"); }
-
     return max;
 }
 
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+pub fn position_hash(board: &Board) -> u64 {
+    board.hash
+}
+
+// Positions before the last capture or pawn move can't reoccur, so only the last `halfmove_clock` plies are searched
+fn is_repetition(hash: u64, history: &[u64], halfmove_clock: u16) -> bool {
+    history.iter().rev().take(halfmove_clock as usize).any(|h| *h == hash)
 }
 
-pub fn nega_max_ab(board: &Board, transposition_table: &mut HashMap<u64, PositionDescription>, depth: u8, best_move: &mut (i8, (i8, FigureType))) -> i32 {
-    let sys_time = SystemTime::now();
-    let res = nega_max_ab_rec(board, transposition_table, depth, depth, -32767, 32767, best_move);
-    println!("{}ms", sys_time.elapsed().unwrap().as_millis());
+// `history` holds the hashes of all positions of the game so far, including the current one
+pub fn repetition_count(board: &Board, history: &[u64]) -> usize {
+    let hash = position_hash(board);
+    history.iter().rev().take(board.halfmove_clock as usize + 1).filter(|h| **h == hash).count()
+}
 
-    return res;
+fn has_legal_move(board: &Board) -> bool {
+    !generate_legal_moves(board).is_empty()
 }
 
+// `history` holds the hashes of all positions of the game so far, including the current one
+pub fn game_status(board: &Board, history: &[u64]) -> GameStatus {
+    if !has_legal_move(board) {
+        if is_king_checked(board.active, board) {
+            let winner = if board.active == FigureColor::WHITE { FigureColor::BLACK } else { FigureColor::WHITE };
+            return GameStatus::Checkmate(winner);
+        }
+        return GameStatus::Stalemate;
+    }
+
+    if board.halfmove_clock >= 100 { return GameStatus::FiftyMoveRule; }
 
+    if repetition_count(board, history) >= 3 { return GameStatus::ThreefoldRepetition; }
 
-fn nega_max_ab_rec(board: &Board, transposition_table: &mut HashMap<u64, PositionDescription>, depth: u8, max_depth: u8, alpha: i32, beta: i32, best_move: &mut (i8, (i8, FigureType))) -> i32 {
-    if depth == 0 { return evaluate_position(board); }
+    if board.is_insufficient_material() { return GameStatus::InsufficientMaterial; }
+
+    return GameStatus::Ongoing;
+}
+
+pub fn nega_max_ab(board: &Board, history: &[u64], transposition_table: &mut HashMap<u64, PositionDescription>, depth: u8, best_move: &mut Option<Move>) -> i32 {
+    let mut path = history.to_vec();
+    let mut board = *board;
+    return nega_max_ab_rec(&mut board, &mut path, transposition_table, depth, depth, -32767, 32767, best_move).0;
+}
+
+// `path` holds the hashes of the game history followed by the positions on the current search path.
+// Returns the score and whether it depends on a repetition of that path.
+#[allow(clippy::too_many_arguments, clippy::manual_is_multiple_of)]
+fn nega_max_ab_rec(board: &mut Board, path: &mut Vec<u64>, transposition_table: &mut HashMap<u64, PositionDescription>, depth: u8, max_depth: u8, alpha: i32, beta: i32, best_move: &mut Option<Move>) -> (i32, bool) {
+    if depth == 0 { return (evaluate_position(board), false); }
 
     let mut max = alpha;
-    let mut can_move = false;
-
-    for index in 0..64i8 {
-        let field = board.fields[index as usize];
-        if field.figure_color == board.active {
-            let legal_moves = calc_legal_moves(index as i8, &board);
-            if legal_moves.len() > 0 { can_move = true; }
-            for m in legal_moves {
-                let mut board_cpy = board.clone();
-                play_move(index as i8, m, &mut board_cpy);
-                let mut score = 0;
-                let mut position_found = false;
-
-                let hash = calculate_hash(&board_cpy);
-                if transposition_table.contains_key(&hash) {
-                    let position_desc = &transposition_table[&hash];
-                        if position_desc.search_depth >= depth {
-                        score = position_desc.score;
-                        position_found = true;
-                    }
-                }
-
-                if !position_found {
-                    score = -nega_max_ab_rec(&board_cpy, transposition_table, depth-1, max_depth, -beta, -max, best_move);
-                    if depth % 2 == 0 { score += 1; } else { score -= 1; } 
-                    transposition_table.insert(hash, PositionDescription { score: score, search_depth: depth });
-                }
-
-
-                if score > max {
-                    max = score;
-                    if depth == max_depth {
-                        *best_move = (index, m);
-                    }
-                    if max >= beta { return max; }
-                }
+    let mut path_dependent = false;
+    let legal_moves = generate_legal_moves(board);
+
+    for m in legal_moves.iter().cloned() {
+        let undo = play_move(m, board);
+        let mut score = 0;
+        let mut position_found = false;
+
+        let hash = board.hash;
+        if is_repetition(hash, path, board.halfmove_clock) {
+            // repeating a position is a draw, the score depends on the path so it is not stored
+            score = 0;
+            position_found = true;
+            path_dependent = true;
+        } else if transposition_table.contains_key(&hash) {
+            let position_desc = &transposition_table[&hash];
+                if position_desc.search_depth >= depth {
+                score = position_desc.score;
+                position_found = true;
             }
         }
+
+        if !position_found {
+            path.push(hash);
+            let (child_score, child_path_dependent) = nega_max_ab_rec(board, path, transposition_table, depth-1, max_depth, -beta, -max, best_move);
+            path.pop();
+            score = -child_score;
+            if depth % 2 == 0 { score += 1; } else { score -= 1; } 
+            // neither are scores that saw a repetition further down
+            if child_path_dependent {
+                path_dependent = true;
+            } else {
+                transposition_table.insert(hash, PositionDescription { score: score, search_depth: depth });
+            }
+        }
+        unmake_move(m, undo, board);
+
+        if score > max {
+            max = score;
+            if depth == max_depth {
+                *best_move = Some(m);
+            }
+            if max >= beta { return (max, path_dependent); }
+        }
     }
 
-    if !can_move { 
-        if is_king_checked(board.active, *board) {
-            return -32767;
-        } else { return 0; }
+    if legal_moves.is_empty() { 
+        if is_king_checked(board.active, board) {
+            return (-32767, false);
+        } else { return (0, false); }
     }
 
-    return max;
+    return (max, path_dependent);
 }
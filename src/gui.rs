@@ -0,0 +1,368 @@
+use ggez::*;
+use ggez::graphics::Text;
+use ggez::graphics::Color;
+use ggez::input;
+use ggez::input::keyboard::{KeyCode, KeyMods};
+use mint::{Point2, Vector2};
+use std::vec::Vec;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Instant;
+
+use chess::engine::calc_legal_moves;
+use chess::engine::play_move;
+//use chess::engine::nega_max;
+use chess::engine::nega_max_ab;
+use chess::engine::{game_status, position_hash, AttackMap};
+use chess::models::{FigureType, FigureColor, Board, Move, PositionDescription, GameStatus};
+use chess::utils::board_to_fen;
+use chess::square::{File, Rank, Square};
+use chess::san::move_to_san;
+use chess::pgn::Game;
+
+pub struct Figure {
+    pub image: graphics::Image,
+    pub figure_type: FigureType,
+    pub figure_color: FigureColor,
+}
+
+struct PromotionState {
+    moves: Vec<Move>,
+    figure_type: FigureType,
+    show_menu: bool
+}
+
+struct State {
+    dt: std::time::Duration, 
+    board: Board,    
+    figures: [Figure; 13],
+    source_field: Option<Square>,
+    legal_moves: Vec<Move>,
+    promo_state: PromotionState,
+    transposition_table: HashMap<u64, PositionDescription>,
+    history: Vec<u64>,
+    status: GameStatus,
+    move_list: Vec<String>,
+    game: Game,
+    // attack counts of both colors on every field, toggled with C
+    show_control: bool,
+}
+
+impl State {
+    fn new(ctx: &mut Context, game: Game) -> GameResult<State> {
+        println!("Creating figures");
+        let figures: [Figure; 13] = [
+            Figure { image: graphics::Image::new(ctx, "/king_b.png").unwrap(), figure_type: FigureType::KING, figure_color: FigureColor::BLACK },
+            Figure { image: graphics::Image::new(ctx, "/queen_b.png").unwrap(), figure_type: FigureType::QUEEN, figure_color: FigureColor::BLACK },
+            Figure { image: graphics::Image::new(ctx, "/bishop_b.png").unwrap(), figure_type: FigureType::BISHOP, figure_color: FigureColor::BLACK },
+            Figure { image: graphics::Image::new(ctx, "/knight_b.png").unwrap(), figure_type: FigureType::KNIGHT, figure_color: FigureColor::BLACK },
+            Figure { image: graphics::Image::new(ctx, "/rook_b.png").unwrap(), figure_type: FigureType::ROOK, figure_color: FigureColor::BLACK },
+            Figure { image: graphics::Image::new(ctx, "/pawn_b.png").unwrap(), figure_type: FigureType::PAWN, figure_color: FigureColor::BLACK },
+            Figure { image: graphics::Image::new(ctx, "/king_w.png").unwrap(), figure_type: FigureType::KING, figure_color: FigureColor::WHITE },
+            Figure { image: graphics::Image::new(ctx, "/queen_w.png").unwrap(), figure_type: FigureType::QUEEN, figure_color: FigureColor::WHITE },
+            Figure { image: graphics::Image::new(ctx, "/bishop_w.png").unwrap(), figure_type: FigureType::BISHOP, figure_color: FigureColor::WHITE },
+            Figure { image: graphics::Image::new(ctx, "/knight_w.png").unwrap(), figure_type: FigureType::KNIGHT, figure_color: FigureColor::WHITE },
+            Figure { image: graphics::Image::new(ctx, "/rook_w.png").unwrap(), figure_type: FigureType::ROOK, figure_color: FigureColor::WHITE },
+            Figure { image: graphics::Image::new(ctx, "/pawn_w.png").unwrap(), figure_type: FigureType::PAWN, figure_color: FigureColor::WHITE },
+            Figure { image: graphics::Image::solid(ctx, 1, Color::from_rgba(0,0,0,0)).unwrap(), figure_type: FigureType::NONE, figure_color: FigureColor::NONE },
+        ];
+        println!("done!");
+
+        let positions = game.replay();
+        let board = *positions.last().unwrap();
+        let history: Vec<u64> = positions.iter().map(position_hash).collect();
+        let status = game_status(&board, &history);
+        // the moves of an opened game are listed like the ones played in the window
+        let move_list = game.mainline().iter().zip(positions.iter()).enumerate()
+            .map(|(index, (m, position))| numbered_san(move_to_san(*m, position), position, index == 0))
+            .collect();
+
+        let s = State {
+            dt: std::time::Duration::new(0,0),
+            board : board,
+            figures: figures,
+            source_field: None,
+            legal_moves: Vec::new(),
+            promo_state: PromotionState { moves: Vec::new(), show_menu: false, figure_type: FigureType::NONE },
+            transposition_table: HashMap::new(),
+            history: history,
+            status: status,
+            move_list: move_list,
+            game: game,
+            show_control: false,
+        };
+        Ok(s)
+    }
+
+    fn play(&mut self, m: Move) {
+        let san = numbered_san(move_to_san(m, &self.board), &self.board, self.move_list.is_empty());
+        self.move_list.push(san);
+
+        self.game.push_move(m, &self.board);
+        play_move(m, &mut self.board);
+        self.history.push(position_hash(&self.board));
+        self.status = game_status(&self.board, &self.history);
+        self.game.set_result(self.status);
+        if self.status != GameStatus::Ongoing { println!("{}", self.status); }
+    }
+
+    fn play_engine_move(&mut self) {
+        if self.status != GameStatus::Ongoing { return; }
+
+        let mut best_move = None;
+        let start = Instant::now();
+        //nega_max(&self.board, 4, &mut best_move);
+        nega_max_ab(&self.board, &self.history, &mut self.transposition_table, 7, &mut best_move);
+        println!("{}ms", start.elapsed().as_millis());
+        if let Some(m) = best_move {
+            self.play(m);
+            println!("{}", board_to_fen(&self.board));
+        }
+    }
+}
+
+// Numbered like in a PGN movetext, "1. e4 e5 2. Nf3", `board` is the position before the move
+fn numbered_san(san: String, board: &Board, first: bool) -> String {
+    if board.active == FigureColor::WHITE {
+        format!("{}. {}", board.fullmove_number, san)
+    } else if first {
+        format!("{}... {}", board.fullmove_number, san)
+    } else {
+        san
+    }
+}
+
+const CHECKER_1: Color = Color{r: 0.431, g: 0.313, b: 0.313, a: 1.0};
+const CHECKER_2: Color = Color{r: 0.878, g: 0.756, b: 0.756, a: 1.0};
+const HIGHLIGHT: Color = Color{r: 0.043, g: 0.530, b: 0.016, a: 0.8};
+
+const CONTROL_WHITE: Color = Color{r: 0.0, g: 0.0, b: 1.0, a: 0.35};
+const CONTROL_BLACK: Color = Color{r: 1.0, g: 0.0, b: 0.0, a: 0.35};
+
+// The field under a point of the window, a8 is drawn in the top left corner
+fn square_at(x: f32, y: f32) -> Option<Square> {
+    if x < 0.0 || y < 0.0 { return None; }
+    let file = File::new((x / 100.0) as u8)?;
+    let rank = Rank::new(7u8.checked_sub((y / 100.0) as u8)?)?;
+    Some(Square::from_coords(file, rank))
+}
+
+fn draw_board(ctx: &mut Context, board: &Board, figures: &[Figure; 13], source_field: Option<Square>, legal_moves: &[Move], control: Option<&AttackMap>) {
+        for square in Square::ALL.iter().cloned() {
+            let (i, j) = (square.file().index(), 7 - square.rank().index());
+            let color = if (i+j) % 2 != 0 { CHECKER_1 } else { CHECKER_2 };
+
+            // draw checkers
+            let rectangle = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), graphics::Rect::new(i as f32 * 100.0, j as f32 * 100.0, 100.0, 100.0), color);
+            graphics::draw(ctx, &rectangle.unwrap(), graphics::DrawParam::default()).unwrap();
+
+            // control overlay: blue where white attacks the field more often, red where black does
+            if let Some(control) = control {
+                let (white, black) = (control.count(square, FigureColor::WHITE), control.count(square, FigureColor::BLACK));
+                let tint = if white > black { Some(CONTROL_WHITE) } else if black > white { Some(CONTROL_BLACK) } else { None };
+                if let Some(tint) = tint {
+                    let rectangle = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), graphics::Rect::new(i as f32 * 100.0, j as f32 * 100.0, 100.0, 100.0), tint);
+                    graphics::draw(ctx, &rectangle.unwrap(), graphics::DrawParam::default()).unwrap();
+                }
+                if white + black > 0 {
+                    let text = Text::new(format!("{}:{}", white, black));
+                    let text_dst = Point2 { x: i as f32 * 100.0 + 3.0, y: j as f32 * 100.0 + 84.0 };
+                    graphics::draw(ctx, &text, graphics::DrawParam::default().dest(text_dst).color(Color::BLACK)).unwrap();
+                }
+            }
+
+            // draw figures
+            let field = &board.fields[square.index()];
+            if field.figure_type != FigureType::NONE {
+                for figure in figures {
+                    if figure.figure_type == field.figure_type && figure.figure_color == field.figure_color {
+                        let figure_dst = Point2 { x: i as f32 * 100.0, y: j as f32 * 100.0};
+                        let mut draw_param = graphics::DrawParam::default().dest(figure_dst).scale(Vector2{x: 0.09765625, y: 0.09765625});
+
+                        // draw shadow for dragged figure
+                        if Some(square) == source_field {
+                            draw_param = draw_param.color(Color::from_rgba(0,0,0,160));
+                        }
+
+                        graphics::draw(ctx, &figure.image, draw_param).unwrap();
+                    }
+                }
+            }
+
+            // highlight legal moves
+            if legal_moves.iter().any(|legal_move| legal_move.to == square) {
+                let circle_highlight = graphics::Mesh::new_circle(ctx, graphics::DrawMode::fill(), [i as f32 * 100.0 + 50.0, j as f32 * 100.0 + 50.0 ], 20.0, 0.01, HIGHLIGHT);
+                graphics::draw(ctx, &circle_highlight.unwrap(), graphics::DrawParam::default()).unwrap();
+            }
+
+        }
+}
+
+fn draw_promotion_menu(ctx: &mut Context, figures: &[Figure; 13]) {
+    let rectangle = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), graphics::Rect::new(195.0, 295.0, 410.0, 110.0), graphics::Color::BLACK);
+    graphics::draw(ctx, &rectangle.unwrap(), graphics::DrawParam::default()).unwrap();
+    let rectangle = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), graphics::Rect::new(200.0, 300.0, 400.0, 100.0), graphics::Color::WHITE);
+    graphics::draw(ctx, &rectangle.unwrap(), graphics::DrawParam::default()).unwrap();
+
+    graphics::draw(ctx, &figures[1].image, graphics::DrawParam::default().dest(Vector2{x: 200.0, y: 300.0}).scale(Vector2{x: 0.09765625, y: 0.09765625})).unwrap();
+    graphics::draw(ctx, &figures[2].image, graphics::DrawParam::default().dest(Vector2{x: 300.0, y: 300.0}).scale(Vector2{x: 0.09765625, y: 0.09765625})).unwrap();
+    graphics::draw(ctx, &figures[3].image, graphics::DrawParam::default().dest(Vector2{x: 400.0, y: 300.0}).scale(Vector2{x: 0.09765625, y: 0.09765625})).unwrap();
+    graphics::draw(ctx, &figures[4].image, graphics::DrawParam::default().dest(Vector2{x: 500.0, y: 300.0}).scale(Vector2{x: 0.09765625, y: 0.09765625})).unwrap();
+}
+
+fn draw_floating_figure(ctx: &mut Context, board: &Board, figures: &[Figure; 13], source_field: Option<Square>) {
+    if let Some(source_field) = source_field {
+        input::mouse::set_cursor_hidden(ctx, true);
+        let field = &board.fields[source_field.index()];
+        for figure in figures {
+            if figure.figure_type == field.figure_type && figure.figure_color == field.figure_color {
+                let mut mouse_position = input::mouse::position(ctx);
+                mouse_position.x -= 50.0;
+                mouse_position.y -= 50.0;
+                graphics::draw(ctx, &figure.image, graphics::DrawParam::default().dest(mouse_position).scale(Vector2{x: 0.09765625, y: 0.09765625})).unwrap();
+            }
+        }
+    } else {
+        input::mouse::set_cursor_hidden(ctx, false);
+    }
+}
+
+impl ggez::event::EventHandler<GameError> for State {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.dt = timer::delta(ctx);
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, Color::BLACK);
+
+        let control = if self.show_control { Some(AttackMap::new(&self.board)) } else { None };
+        draw_board(ctx, &self.board, &self.figures, self.source_field, &self.legal_moves, control.as_ref());
+
+        draw_floating_figure(ctx, &self.board, &self.figures, self.source_field);
+
+        if self.promo_state.show_menu {
+            draw_promotion_menu(ctx, &self.figures);
+        }
+        let text = Text::new(format!("{} FPS", 1000000000 / self.dt.as_nanos()));
+        let text_dst = Point2 { x: 5.0, y: 5.0};
+        graphics::draw(ctx, &text, graphics::DrawParam::default().dest(text_dst).color(Color::BLACK))?;
+
+        if self.status != GameStatus::Ongoing {
+            let text = Text::new(self.status.to_string());
+            let text_dst = Point2 { x: 5.0, y: 25.0};
+            graphics::draw(ctx, &text, graphics::DrawParam::default().dest(text_dst).color(Color::BLACK))?;
+        }
+
+        // the last moves of the game
+        if !self.move_list.is_empty() {
+            let text = Text::new(self.move_list[self.move_list.len().saturating_sub(8)..].join(" "));
+            let text_dst = Point2 { x: 5.0, y: 780.0};
+            graphics::draw(ctx, &text, graphics::DrawParam::default().dest(text_dst).color(Color::BLACK))?;
+        }
+
+        graphics::present(ctx)?;
+        Ok(())
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        _btn: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) {
+        if !self.promo_state.show_menu && self.status == GameStatus::Ongoing {
+            let square = match square_at(x, y) {
+                Some(square) => square,
+                None => return
+            };
+            let source_field = self.board.fields[square.index()];
+            if source_field.figure_type != FigureType::NONE && source_field.figure_color == self.board.active {
+                self.source_field = Some(square);
+                self.legal_moves = calc_legal_moves(square, &self.board);
+            }
+        }
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        _btn: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) {
+        if !self.promo_state.show_menu {
+            if self.source_field.is_none() { return; }
+
+            let target_field = square_at(x, y);
+
+            let selected_move: Vec<Move> = self.legal_moves.iter().filter(|target_move| Some(target_move.to) == target_field).cloned().collect();
+            if selected_move.len() == 1 {
+                self.play(selected_move[0]);
+                self.play_engine_move();
+            } else if selected_move.len() > 1 {
+                self.promo_state.moves = selected_move;
+                self.promo_state.figure_type = FigureType::NONE;
+                self.promo_state.show_menu = true;
+            }
+            
+            self.source_field = None;
+            self.legal_moves = Vec::new();
+        } else {
+            // the menu covers c5 to f5
+            match square_at(x, y).filter(|square| square.rank() == Rank::FIFTH).map(|square| square.file()) {
+                Some(File::C) => self.promo_state.figure_type = FigureType::QUEEN,
+                Some(File::D) => self.promo_state.figure_type = FigureType::BISHOP,
+                Some(File::E) => self.promo_state.figure_type = FigureType::KNIGHT,
+                Some(File::F) => self.promo_state.figure_type = FigureType::ROOK,
+                _ => ()
+            }
+
+            if self.promo_state.figure_type != FigureType::NONE {
+                self.promo_state.show_menu = false;
+                let figure_type = self.promo_state.figure_type;
+                if let Some(m) = self.promo_state.moves.iter().find(|m| m.promotion == figure_type) {
+                    self.play(*m);
+                    self.play_engine_move();
+                }
+            }
+        }
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
+        match keycode {
+            KeyCode::C => self.show_control = !self.show_control,
+            KeyCode::Escape => event::quit(ctx),
+            _ => ()
+        }
+    }
+
+    // keep the game, every game played in the window is appended to games.pgn
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        if self.game.moves.is_empty() { return false; }
+
+        let written = std::fs::OpenOptions::new().create(true).append(true).open("games.pgn")
+            .and_then(|mut file| writeln!(file, "{}", self.game.to_pgn()));
+        match written {
+            Ok(()) => println!("game saved to games.pgn"),
+            Err(err) => eprintln!("could not save the game to games.pgn: {}", err)
+        }
+        return false;
+    }
+}
+
+// Opens the window and lets the human play the side to move of `game` against the engine
+pub fn run(game: Game) -> GameResult {
+    let c = conf::Conf::default().window_mode(conf::WindowMode::default().dimensions(800.0, 800.0));
+
+    let (mut ctx, event_loop) = ContextBuilder::new("hello_ggez", "Was weiss ich")
+        .default_conf(c)
+        .window_setup(
+            conf::WindowSetup::default().samples(conf::NumSamples::Eight))
+        .build()
+        .unwrap();
+
+    let state = State::new(&mut ctx, game)?;
+    event::run(ctx, event_loop, state);
+}
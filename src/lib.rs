@@ -0,0 +1,17 @@
+#![allow(clippy::needless_return, clippy::upper_case_acronyms, clippy::redundant_field_names)]
+
+// Rules, notation and search of rust-chess, the ggez front end lives in the binary behind the `gui` feature
+
+pub mod engine;
+pub mod attacks;
+pub mod bitboard;
+pub mod square;
+pub mod zobrist;
+
+pub mod models;
+pub mod utils;
+pub mod san;
+pub mod pgn;
+pub mod perft;
+
+mod tests;
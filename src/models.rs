@@ -1,4 +1,11 @@
-use ggez::graphics;
+use std::fmt;
+use std::ops::Deref;
+
+use crate::bitboard::{bit, BitIter};
+use crate::zobrist::piece_key;
+use crate::engine::{generate_legal_moves, is_king_checked};
+use crate::utils::board_to_fen;
+use crate::square::{File, Rank, Square};
 
 #[derive(Clone, Copy, PartialEq, Debug, Hash)]
 pub enum FigureColor {
@@ -18,12 +25,6 @@ pub enum FigureType {
     NONE
 }
 
-pub struct Figure {
-    pub image: graphics::Image,
-    pub figure_type: FigureType,
-    pub figure_color: FigureColor,
-}
-
 #[derive(Clone, Copy, Debug, Hash)]
 pub struct Field {
     pub figure_type: FigureType,
@@ -36,15 +37,365 @@ pub struct CastleRights {
     pub K: bool,
     pub Q: bool,
     pub k: bool,
-    pub q: bool
+    pub q: bool,
+    // files of the castling rooks indexed by color, h and a in standard chess. A setup position can
+    // have them on different files for both sides, Chess960 start positions mirror them.
+    pub king_side_file: [File; 2],
+    pub queen_side_file: [File; 2]
 }
 
-#[derive(Clone, Copy, Debug, Hash)]
+// Castle moves go from the king to the castling rook, `to` is the rook's field and not the king's destination
+#[derive(Clone, Copy, PartialEq, Debug, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: FigureType,
+    pub flags: u8
+}
+
+impl Move {
+    pub const CAPTURE: u8 = 1;
+    pub const CASTLE: u8 = 2;
+    pub const EN_PASSANT: u8 = 4;
+    pub const DOUBLE_PUSH: u8 = 8;
+
+    pub fn is_capture(&self) -> bool { self.flags & Move::CAPTURE != 0 }
+    pub fn is_castle(&self) -> bool { self.flags & Move::CASTLE != 0 }
+    pub fn is_en_passant(&self) -> bool { self.flags & Move::EN_PASSANT != 0 }
+    pub fn is_double_push(&self) -> bool { self.flags & Move::DOUBLE_PUSH != 0 }
+
+    // Long algebraic notation as used by UCI, "e2e4" or "e7e8q". Castling is written as the king's
+    // two-field step ("e1g1"), or as king takes rook ("e1h1") in Chess960 mode.
+    pub fn to_uci(self, chess960: bool) -> String {
+        let mut to = self.to;
+        if self.is_castle() && !chess960 {
+            let king_file = if self.to.file() > self.from.file() { File::G } else { File::C };
+            to = Square::from_coords(king_file, self.from.rank());
+        }
+
+        let mut uci = format!("{}{}", self.from, to);
+        match self.promotion {
+            FigureType::QUEEN => uci.push('q'),
+            FigureType::ROOK => uci.push('r'),
+            FigureType::BISHOP => uci.push('b'),
+            FigureType::KNIGHT => uci.push('n'),
+            _ => ()
+        }
+        return uci;
+    }
+
+    // The legal move of `board` written as `uci`, castling is accepted in both notations
+    pub fn from_uci(board: &Board, uci: &str) -> Option<Move> {
+        let legal_moves = generate_legal_moves(board);
+        // a plain king move wins over castling, in Chess960 the king may step onto the castling field
+        legal_moves.iter().find(|m| !m.is_castle() && m.to_uci(false) == uci)
+            .or_else(|| legal_moves.iter().find(|m| m.is_castle() && (m.to_uci(false) == uci || m.to_uci(true) == uci)))
+            .cloned()
+    }
+}
+
+// Fixed capacity move list that lives on the stack, no position has more than 218 legal moves
+#[derive(Clone, Copy)]
+pub struct MoveList {
+    moves: [Move; 256],
+    len: usize
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList { moves: [Move { from: Square::ALL[0], to: Square::ALL[0], promotion: FigureType::NONE, flags: 0 }; 256], len: 0 }
+    }
+
+    pub fn push(&mut self, m: Move) {
+        self.moves[self.len] = m;
+        self.len += 1;
+    }
+
+    pub fn retain<F: FnMut(&Move) -> bool>(&mut self, mut f: F) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if f(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> MoveList {
+        MoveList::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves[..self.len].iter()
+    }
+}
+
+impl fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Everything `play_move` can't derive from the move itself when taking it back
+#[derive(Clone, Copy, Debug)]
+pub struct Undo {
+    pub captured: Field,
+    pub castle_rights: CastleRights,
+    pub en_passant: Option<Square>,
+    pub halfmove_clock: u16,
+    pub fullmove_number: u16,
+    pub hash: u64
+}
+
+// `fields` and the bitboards describe the same position, fields must only be written through `set_field`
+#[derive(Clone, Copy, Debug)]
 pub struct Board {
     pub fields: [Field; 64],    
+    pub pieces: [u64; 6],
+    pub colors: [u64; 2],
     pub active: FigureColor,
     pub castle_rights: CastleRights,
-    pub en_passant: i8
+    pub en_passant: Option<Square>,
+    pub halfmove_clock: u16,
+    pub fullmove_number: u16,
+    pub hash: u64,
+    // Chess960 positions write castling as king takes rook and castle rights with rook files
+    pub chess960: bool
+}
+
+impl Board {
+    pub fn set_field(&mut self, square: Square, field: Field) {
+        let bit = bit(square);
+        let old_field = self.fields[square.index()];
+        if old_field.figure_type != FigureType::NONE { self.pieces[old_field.figure_type as usize] &= !bit; }
+        if old_field.figure_color != FigureColor::NONE { self.colors[old_field.figure_color as usize] &= !bit; }
+        if field.figure_type != FigureType::NONE { self.pieces[field.figure_type as usize] |= bit; }
+        if field.figure_color != FigureColor::NONE { self.colors[field.figure_color as usize] |= bit; }
+        self.hash ^= piece_key(old_field.figure_type, old_field.figure_color, square) ^ piece_key(field.figure_type, field.figure_color, square);
+        self.fields[square.index()] = field;
+    }
+
+    pub fn king_square(&self, figure_color: FigureColor) -> Option<Square> {
+        BitIter(self.pieces_of(FigureType::KING, figure_color)).next()
+    }
+
+    pub fn occupied(&self) -> u64 {
+        self.colors[0] | self.colors[1]
+    }
+
+    pub fn pieces_of(&self, figure_type: FigureType, figure_color: FigureColor) -> u64 {
+        self.pieces[figure_type as usize] & self.colors[figure_color as usize]
+    }
+
+    // Dead positions: K vs K, K+minor vs K and king and bishops only with all bishops on the same square color
+    pub fn is_insufficient_material(&self) -> bool {
+        let mut minors = 0;
+        let mut bishop_square_colors = [false; 2];
+        let mut knights = 0;
+
+        if self.pieces[FigureType::PAWN as usize] | self.pieces[FigureType::ROOK as usize] | self.pieces[FigureType::QUEEN as usize] != 0 { return false; }
+
+        for square in BitIter(self.pieces[FigureType::BISHOP as usize]) {
+            minors += 1;
+            bishop_square_colors[(square.file().index() + square.rank().index()) % 2] = true;
+        }
+        for _ in BitIter(self.pieces[FigureType::KNIGHT as usize]) {
+            minors += 1;
+            knights += 1;
+        }
+
+        if minors <= 1 { return true; }
+        return knights == 0 && !(bishop_square_colors[0] && bishop_square_colors[1]);
+    }
+
+    // Everything that makes the position impossible to reach from a start position, empty if it is fine.
+    // Positions that fail here can confuse the move generator and the search.
+    pub fn validate(&self) -> Vec<PositionError> {
+        let mut errors = Vec::new();
+
+        for color in [FigureColor::WHITE, FigureColor::BLACK].iter() {
+            let kings = self.pieces_of(FigureType::KING, *color).count_ones();
+            if kings != 1 { errors.push(PositionError::KingCount(*color, kings)); }
+        }
+
+        const BACK_RANKS: u64 = 0xff00_0000_0000_00ff;
+        for square in BitIter(self.pieces[FigureType::PAWN as usize] & BACK_RANKS) {
+            errors.push(PositionError::PawnOnBackRank(square));
+        }
+
+        let inactive = if self.active == FigureColor::WHITE { FigureColor::BLACK } else { FigureColor::WHITE };
+        if is_king_checked(inactive, self) { errors.push(PositionError::InactiveKingInCheck(inactive)); }
+
+        let rights = &self.castle_rights;
+        for (c, allowed) in [('K', rights.K), ('Q', rights.Q), ('k', rights.k), ('q', rights.q)].iter() {
+            if !allowed { continue; }
+            let color = if c.is_lowercase() { FigureColor::BLACK } else { FigureColor::WHITE };
+            let back_rank = if color == FigureColor::BLACK { Rank::EIGHTH } else { Rank::FIRST };
+            let king_side = c.eq_ignore_ascii_case(&'k');
+            let rook_file = if king_side { rights.king_side_file[color as usize] } else { rights.queen_side_file[color as usize] };
+
+            // the king starts between the castling rooks, on the e-file unless it is Chess960
+            let king = self.king_square(color).filter(|king| self.pieces_of(FigureType::KING, color).count_ones() == 1 && king.rank() == back_rank);
+            match king {
+                Some(king) if self.chess960 || king.file() == File::E => {
+                    let king_file = king.file();
+                    if (king_side && rook_file < king_file) || (!king_side && rook_file > king_file) || rook_file == king_file {
+                        errors.push(PositionError::CastleRightWithoutRook(*c));
+                        continue;
+                    }
+                },
+                _ => errors.push(PositionError::CastleRightWithoutKing(*c))
+            }
+
+            let rook = self.fields[Square::from_coords(rook_file, back_rank).index()];
+            if rook.figure_type != FigureType::ROOK || rook.figure_color != color {
+                errors.push(PositionError::CastleRightWithoutRook(*c));
+            }
+        }
+
+        // the field a pawn of the inactive side just skipped: empty, on the third rank from its side,
+        // with the pawn in front of it and its starting field behind it empty
+        if let Some(ep) = self.en_passant {
+            let (rank, pawn_step) = if self.active == FigureColor::WHITE { (Rank::SIXTH, -1) } else { (Rank::THIRD, 1) };
+            let is_empty = |square: Square| self.fields[square.index()].figure_type == FigureType::NONE;
+            let possible = ep.rank() == rank && is_empty(ep) && ep.offset(0, -pawn_step).is_some_and(is_empty)
+                && ep.offset(0, pawn_step).is_some_and(|square| {
+                    let pawn = self.fields[square.index()];
+                    pawn.figure_type == FigureType::PAWN && pawn.figure_color == inactive
+                });
+            if !possible { errors.push(PositionError::InvalidEnPassant(ep)); }
+        }
+
+        return errors;
+    }
+
+    // The diagram with the fields of `marked` in brackets, e.g. the targets of the legal moves
+    pub fn marked(&self, marked: u64) -> BoardDiagram<'_> {
+        BoardDiagram { board: self, marked: marked }
+    }
+}
+
+// Diagram of a board with some fields highlighted, made by `Board::marked`. Pieces are written
+// as FEN letters, or as chess symbols with the alternate flag ("{:#}").
+pub struct BoardDiagram<'a> {
+    board: &'a Board,
+    marked: u64
+}
+
+impl fmt::Display for BoardDiagram<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let border = "  +------------------------+";
+        writeln!(f, "{}", border)?;
+        for rank in Rank::ALL.iter().rev() {
+            write!(f, "{} |", rank)?;
+            for file in File::ALL.iter() {
+                let square = Square::from_coords(*file, *rank);
+                let field = self.board.fields[square.index()];
+                let white = field.figure_color == FigureColor::WHITE;
+                let symbol = match (field.figure_type, f.alternate()) {
+                    (FigureType::KING, false) => 'k',
+                    (FigureType::QUEEN, false) => 'q',
+                    (FigureType::ROOK, false) => 'r',
+                    (FigureType::BISHOP, false) => 'b',
+                    (FigureType::KNIGHT, false) => 'n',
+                    (FigureType::PAWN, false) => 'p',
+                    (FigureType::NONE, false) => '.',
+                    (FigureType::KING, true) => if white { '♔' } else { '♚' },
+                    (FigureType::QUEEN, true) => if white { '♕' } else { '♛' },
+                    (FigureType::ROOK, true) => if white { '♖' } else { '♜' },
+                    (FigureType::BISHOP, true) => if white { '♗' } else { '♝' },
+                    (FigureType::KNIGHT, true) => if white { '♘' } else { '♞' },
+                    (FigureType::PAWN, true) => if white { '♙' } else { '♟' },
+                    (FigureType::NONE, true) => '·'
+                };
+                let symbol = if white && !f.alternate() { symbol.to_ascii_uppercase() } else { symbol };
+                if self.marked & bit(square) != 0 { write!(f, "[{}]", symbol)?; } else { write!(f, " {} ", symbol)?; }
+            }
+            writeln!(f, "|")?;
+        }
+        writeln!(f, "{}", border)?;
+        writeln!(f, "    a  b  c  d  e  f  g  h")?;
+        writeln!(f)?;
+
+        let fen = board_to_fen(self.board);
+        let fen_fields: Vec<&str> = fen.split(' ').collect();
+        writeln!(f, "fen: {}", fen)?;
+        write!(f, "{} to move, castling {}, en passant {}, hash {:016x}",
+            if self.board.active == FigureColor::WHITE { "white" } else { "black" }, fen_fields[2], fen_fields[3], self.board.hash)
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.marked(0), f)
+    }
+}
+
+// A reason why a position can't arise in a game, see `Board::validate`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PositionError {
+    KingCount(FigureColor, u32),
+    PawnOnBackRank(Square),
+    InactiveKingInCheck(FigureColor),
+    CastleRightWithoutKing(char),
+    CastleRightWithoutRook(char),
+    InvalidEnPassant(Square),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let color_name = |color: &FigureColor| if *color == FigureColor::WHITE { "white" } else { "black" };
+        match self {
+            PositionError::KingCount(color, count) => write!(f, "{} has {} kings instead of one", color_name(color), count),
+            PositionError::PawnOnBackRank(square) => write!(f, "pawn on the back rank at {}", square),
+            PositionError::InactiveKingInCheck(color) => write!(f, "the {} king is in check but it is not {}'s move", color_name(color), color_name(color)),
+            PositionError::CastleRightWithoutKing(c) => write!(f, "castle right '{}' without the king on its starting field", c),
+            PositionError::CastleRightWithoutRook(c) => write!(f, "castle right '{}' without a rook to castle with", c),
+            PositionError::InvalidEnPassant(square) => write!(f, "en passant field {} does not follow a double pawn push", square),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate(FigureColor),
+    Stalemate,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    InsufficientMaterial
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameStatus::Ongoing => write!(f, "ongoing"),
+            GameStatus::Checkmate(FigureColor::WHITE) => write!(f, "checkmate, white wins"),
+            GameStatus::Checkmate(_) => write!(f, "checkmate, black wins"),
+            GameStatus::Stalemate => write!(f, "draw by stalemate"),
+            GameStatus::FiftyMoveRule => write!(f, "draw by fifty-move rule"),
+            GameStatus::ThreefoldRepetition => write!(f, "draw by threefold repetition"),
+            GameStatus::InsufficientMaterial => write!(f, "draw by insufficient material")
+        }
+    }
 }
 
 pub struct PositionDescription {
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::engine::{generate_legal_moves, play_move, unmake_move};
+use crate::models::{Board, Move};
+use crate::utils::{board_from_fen, FenError};
+
+// Number of leaf nodes of the legal move tree `depth` plies deep
+pub fn perft(board: &mut Board, depth: u8) -> u64 {
+    if depth == 0 { return 1; }
+
+    let legal_moves = generate_legal_moves(board);
+    // the moves of the last ply don't have to be played to be counted
+    if depth == 1 { return legal_moves.len() as u64; }
+
+    let mut nodes = 0;
+    for m in legal_moves.iter().cloned() {
+        let undo = play_move(m, board);
+        nodes += perft(board, depth - 1);
+        unmake_move(m, undo, board);
+    }
+    return nodes;
+}
+
+// Perft split up by the moves of the first ply
+pub fn perft_divide(board: &mut Board, depth: u8) -> Vec<(Move, u64)> {
+    let mut divide = Vec::new();
+    if depth == 0 { return divide; }
+
+    for m in generate_legal_moves(board).iter().cloned() {
+        let undo = play_move(m, board);
+        divide.push((m, perft(board, depth - 1)));
+        unmake_move(m, undo, board);
+    }
+    return divide;
+}
+
+// Reads divide output like Stockfish's "go perft", one "e2e4: 20" per line, everything else is skipped
+pub fn read_divide(text: &str) -> HashMap<String, u64> {
+    let mut divide = HashMap::new();
+    for line in text.lines() {
+        let mut split = line.splitn(2, ':');
+        let (uci, count) = match (split.next(), split.next()) {
+            (Some(uci), Some(count)) => (uci.trim(), count.trim()),
+            _ => continue
+        };
+        if uci.is_empty() || uci.contains(char::is_whitespace) { continue; }
+        if let Ok(count) = count.parse() { divide.insert(uci.to_string(), count); }
+    }
+    return divide;
+}
+
+// Lists the moves whose counts differ from the reference, sorted by move
+pub fn compare_divide(divide: &HashMap<String, u64>, reference: &HashMap<String, u64>) -> Vec<String> {
+    let mut moves: Vec<&String> = divide.keys().chain(reference.keys().filter(|uci| !divide.contains_key(*uci))).collect();
+    moves.sort();
+
+    let mut differences = Vec::new();
+    for uci in moves {
+        match (divide.get(uci), reference.get(uci)) {
+            (Some(count), None) => differences.push(format!("{}: {} but not in the reference", uci, count)),
+            (None, Some(expected)) => differences.push(format!("{}: missing, expected {}", uci, expected)),
+            (Some(count), Some(expected)) if count != expected => differences.push(format!("{}: {}, expected {}", uci, count, expected)),
+            _ => ()
+        }
+    }
+    return differences;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EpdError {
+    InvalidFen(usize, FenError),
+    InvalidCount(usize, String),
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdError::InvalidFen(line, err) => write!(f, "line {}: invalid FEN: {}", line, err),
+            EpdError::InvalidCount(line, count) => write!(f, "line {}: invalid perft count '{}', expected ';D<depth> <nodes>'", line, count),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+// One position of a perft suite with the expected node count per depth
+pub struct PerftCase {
+    pub fen: String,
+    pub board: Board,
+    pub counts: Vec<(u8, u64)>,
+}
+
+#[derive(Debug)]
+pub struct PerftFailure {
+    pub fen: String,
+    pub depth: u8,
+    pub expected: u64,
+    pub nodes: u64,
+}
+
+impl fmt::Display for PerftFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} depth {}: {} nodes, expected {}", self.fen, self.depth, self.nodes, self.expected)
+    }
+}
+
+// Reads a perft suite in the usual EPD format, "<fen> ;D1 20 ;D2 400 ...", empty lines are skipped
+pub fn read_epd(text: &str) -> Result<Vec<PerftCase>, EpdError> {
+    let mut cases = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let mut fields = line.split(';');
+        let fen = fields.next().unwrap_or("").trim();
+        if fen.is_empty() { continue; }
+        let board = board_from_fen(fen).map_err(|err| EpdError::InvalidFen(index + 1, err))?;
+
+        let mut counts = Vec::new();
+        for field in fields {
+            let invalid = || EpdError::InvalidCount(index + 1, field.trim().to_string());
+            let mut split = field.trim().splitn(2, ' ');
+            let depth = split.next().and_then(|depth| depth.strip_prefix('D')).and_then(|depth| depth.parse().ok()).ok_or_else(invalid)?;
+            let nodes = split.next().and_then(|nodes| nodes.trim().parse().ok()).ok_or_else(invalid)?;
+            counts.push((depth, nodes));
+        }
+        cases.push(PerftCase { fen: fen.to_string(), board: board, counts: counts });
+    }
+    return Ok(cases);
+}
+
+// Checks every count of the suite up to `max_depth`, `report` is called once per position with its failures
+pub fn run_suite<F: FnMut(&PerftCase, &[PerftFailure])>(cases: &[PerftCase], max_depth: u8, mut report: F) -> Vec<PerftFailure> {
+    let mut failures = Vec::new();
+    for case in cases.iter() {
+        let mut case_failures = Vec::new();
+        let mut board = case.board;
+        for (depth, expected) in case.counts.iter().filter(|(depth, _)| *depth <= max_depth) {
+            let nodes = perft(&mut board, *depth);
+            if nodes != *expected {
+                case_failures.push(PerftFailure { fen: case.fen.clone(), depth: *depth, expected: *expected, nodes: nodes });
+            }
+        }
+        report(case, &case_failures);
+        failures.append(&mut case_failures);
+    }
+    return failures;
+}
@@ -0,0 +1,431 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::engine::play_move;
+use crate::models::{FigureColor, Board, Move, GameStatus};
+use crate::san::{move_from_san, move_to_san, SanError};
+use crate::utils::{board_from_fen, board_to_fen, FenError, START_FEN};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    InvalidTag(String),
+    UnterminatedComment,
+    UnbalancedVariation,
+    InvalidFen(FenError),
+    InvalidMove(SanError),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag(tag) => write!(f, "invalid tag pair '{}'", tag),
+            PgnError::UnterminatedComment => write!(f, "comment is missing its closing '}}'"),
+            PgnError::UnbalancedVariation => write!(f, "parentheses of a variation don't match"),
+            PgnError::InvalidFen(err) => write!(f, "invalid FEN tag: {}", err),
+            PgnError::InvalidMove(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+// One move of the game tree together with its annotations
+#[derive(Clone, Debug)]
+pub struct GameNode {
+    pub m: Move,
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub comments: Vec<String>,
+    // children[0] continues the line, the others are variations to it
+    pub children: Vec<GameNode>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    // comments in front of the first move
+    pub comments: Vec<String>,
+    // alternatives for the first move, moves[0] starts the mainline
+    pub moves: Vec<GameNode>,
+    pub result: String,
+}
+
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+impl Game {
+    // An empty game with the Seven Tag Roster filled in, games that don't start from the
+    // initial position get the SetUp and FEN tags
+    pub fn new(start: Board) -> Game {
+        let mut tags: Vec<(String, String)> = SEVEN_TAG_ROSTER.iter().map(|name| (name.to_string(), "?".to_string())).collect();
+        tags[2].1 = today();
+        tags[6].1 = "*".to_string();
+
+        let fen = board_to_fen(&start);
+        if start.chess960 { tags.push(("Variant".to_string(), "Chess960".to_string())); }
+        if fen != START_FEN {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), fen));
+        }
+
+        return Game { tags: tags, start: start, comments: Vec::new(), moves: Vec::new(), result: "*".to_string() };
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn mainline(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut nodes = &self.moves;
+        while let Some(node) = nodes.first() {
+            moves.push(node.m);
+            nodes = &node.children;
+        }
+        return moves;
+    }
+
+    // All positions of the mainline, starting with `start` and ending with the final position
+    pub fn replay(&self) -> Vec<Board> {
+        let mut board = self.start;
+        let mut positions = vec![board];
+        for m in self.mainline() {
+            play_move(m, &mut board);
+            positions.push(board);
+        }
+        return positions;
+    }
+
+    // Appends `m` to the mainline, `board` is the final position of the game that `m` is played in.
+    // Callers recording a game already have it, replaying the mainline on every move would be quadratic.
+    pub fn push_move(&mut self, m: Move, board: &Board) {
+        let node = GameNode { m: m, san: move_to_san(m, board), nags: Vec::new(), comments: Vec::new(), children: Vec::new() };
+
+        let mut nodes = &mut self.moves;
+        while !nodes.is_empty() {
+            let mainline = nodes;
+            nodes = &mut mainline[0].children;
+        }
+        nodes.push(node);
+    }
+
+    pub fn set_result(&mut self, status: GameStatus) {
+        let result = match status {
+            GameStatus::Ongoing => "*",
+            GameStatus::Checkmate(FigureColor::WHITE) => "1-0",
+            GameStatus::Checkmate(_) => "0-1",
+            _ => "1/2-1/2"
+        };
+        self.result = result.to_string();
+        self.set_tag("Result", result);
+    }
+
+    // Export format: the Seven Tag Roster first, then the other tags and the movetext
+    // with its comments, NAGs and variations, wrapped at 80 characters
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for name in SEVEN_TAG_ROSTER.iter() {
+            let value = if *name == "Result" { self.result.as_str() } else { self.tag(name).unwrap_or("?") };
+            pgn.push_str(&format_tag(name, value));
+        }
+        for (name, value) in self.tags.iter().filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str())) {
+            pgn.push_str(&format_tag(name, value));
+        }
+        pgn.push('\n');
+
+        let mut tokens: Vec<String> = self.comments.iter().map(|comment| format!("{{{}}}", comment)).collect();
+        if !self.moves.is_empty() { write_moves(&mut tokens, &self.moves, &self.start, true); }
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for (i, token) in tokens.iter().enumerate() {
+            let glued = i > 0 && (tokens[i-1] == "(" || token == ")");
+            if !line.is_empty() && !glued && line.len() + token.len() + 1 > 80 {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            } else if !line.is_empty() && !glued {
+                line.push(' ');
+            }
+            line.push_str(token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+
+        return pgn;
+    }
+}
+
+fn format_tag(name: &str, value: &str) -> String {
+    format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Writes alternatives[0] and the line following it, the other alternatives become variations.
+// Black moves only get a move number at the start of a line or after a comment or variation.
+fn write_moves(tokens: &mut Vec<String>, alternatives: &[GameNode], board: &Board, show_number: bool) {
+    let node = &alternatives[0];
+    if board.active == FigureColor::WHITE {
+        tokens.push(format!("{}.", board.fullmove_number));
+    } else if show_number {
+        tokens.push(format!("{}...", board.fullmove_number));
+    }
+    tokens.push(node.san.clone());
+    for nag in node.nags.iter() { tokens.push(format!("${}", nag)); }
+    for comment in node.comments.iter() { tokens.push(format!("{{{}}}", comment)); }
+
+    for variation in alternatives[1..].iter() {
+        tokens.push("(".to_string());
+        write_moves(tokens, std::slice::from_ref(variation), board, true);
+        tokens.push(")".to_string());
+    }
+
+    if !node.children.is_empty() {
+        let mut next = *board;
+        play_move(node.m, &mut next);
+        write_moves(tokens, &node.children, &next, !node.comments.is_empty() || alternatives.len() > 1);
+    }
+}
+
+// The current date as "YYYY.MM.DD"
+fn today() -> String {
+    let seconds = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+    // days since 1970-01-01 to the proleptic Gregorian calendar, eras of 400 years
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return format!("{:04}.{:02}.{:02}", year, month, day);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(String),
+    San(String),
+}
+
+// Suffix annotations are turned into their numeric annotation glyphs
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None
+    }
+}
+
+fn parse_tag(tag: &str) -> Result<Token, PgnError> {
+    let invalid = || PgnError::InvalidTag(tag.to_string());
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).ok_or_else(invalid)?;
+    let value = tag[name_end..].trim();
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') { return Err(invalid()); }
+
+    // values escape quotes and backslashes with a backslash
+    let mut unescaped = String::new();
+    let mut chars = value[1..value.len()-1].chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' { chars.next().ok_or_else(invalid)? } else { c });
+    }
+    return Ok(Token::Tag(tag[..name_end].to_string(), unescaped));
+}
+
+// Position of the ']' closing the tag that starts `chars`, brackets inside the quoted value don't count
+fn tag_end(chars: &[char]) -> Option<usize> {
+    let mut quoted = false;
+    let mut i = 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if quoted => i += 1,
+            '"' => quoted = !quoted,
+            ']' if !quoted => return Some(i),
+            _ => ()
+        }
+        i += 1;
+    }
+    return None;
+}
+
+fn tokenize(pgn: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = pgn.chars().collect();
+    let mut i = 0;
+    let mut line_start = true;
+
+    while i < chars.len() {
+        let c = chars[i];
+        // lines starting with '%' are escaped and ignored
+        if line_start && c == '%' {
+            while i < chars.len() && chars[i] != '\n' { i += 1; }
+            continue;
+        }
+        line_start = c == '\n';
+
+        match c {
+            '[' => {
+                let end = tag_end(&chars[i..]).ok_or_else(|| PgnError::InvalidTag(chars[i..].iter().collect()))?;
+                tokens.push(parse_tag(&chars[i+1..i+end].iter().collect::<String>())?);
+                i += end + 1;
+            },
+            '{' => {
+                let end = chars[i..].iter().position(|c| *c == '}').ok_or(PgnError::UnterminatedComment)?;
+                tokens.push(Token::Comment(chars[i+1..i+end].iter().collect::<String>().trim().to_string()));
+                i += end + 1;
+            },
+            ';' => {
+                let end = chars[i..].iter().position(|c| *c == '\n').unwrap_or(chars.len() - i);
+                tokens.push(Token::Comment(chars[i+1..i+end].iter().collect::<String>().trim().to_string()));
+                i += end;
+            },
+            '(' => { tokens.push(Token::VariationStart); i += 1; },
+            ')' => { tokens.push(Token::VariationEnd); i += 1; },
+            '$' => {
+                let digits: String = chars[i+1..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                i += digits.len() + 1;
+                if let Ok(nag) = digits.parse() { tokens.push(Token::Nag(nag)); }
+            },
+            _ if c.is_whitespace() => i += 1,
+            _ => {
+                let symbol: String = chars[i..].iter().take_while(|c| !c.is_whitespace() && !"[]{};()$".contains(**c)).collect();
+                i += symbol.chars().count();
+
+                match symbol.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => { tokens.push(Token::Result(symbol)); continue; },
+                    _ => ()
+                }
+
+                // move numbers, "12." and "12..." possibly glued to the move
+                let mut san = symbol.as_str();
+                if san.starts_with(|c: char| c.is_ascii_digit()) && san.contains('.') {
+                    san = san.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start_matches('.');
+                }
+
+                let suffix_start = san.find(['!', '?']).unwrap_or(san.len());
+                if suffix_start > 0 { tokens.push(Token::San(san[..suffix_start].to_string())); }
+                if let Some(nag) = suffix_nag(&san[suffix_start..]) { tokens.push(Token::Nag(nag)); }
+            }
+        }
+    }
+
+    return Ok(tokens);
+}
+
+// Parses one line of moves starting in `board` and returns the alternatives for its first move,
+// the line itself comes first and is followed by the variations attached to its first move.
+// Comments in front of the first move end up in `leading_comments`.
+fn parse_line(tokens: &[Token], pos: &mut usize, board: Board, nested: bool, leading_comments: &mut Vec<String>) -> Result<Vec<GameNode>, PgnError> {
+    let mut board = board;
+    let mut board_before = board;
+    let mut nodes: Vec<GameNode> = Vec::new();
+    let mut variations: Vec<Vec<GameNode>> = Vec::new();
+    let mut pending_comments = Vec::new();
+    let mut closed = false;
+
+    while *pos < tokens.len() {
+        let token = &tokens[*pos];
+        *pos += 1;
+        match token {
+            Token::San(san) => {
+                let m = move_from_san(san, &board).map_err(PgnError::InvalidMove)?;
+                board_before = board;
+                play_move(m, &mut board);
+                nodes.push(GameNode { m: m, san: san.clone(), nags: Vec::new(), comments: Vec::new(), children: Vec::new() });
+                variations.push(Vec::new());
+            },
+            Token::Nag(nag) => {
+                if let Some(node) = nodes.last_mut() { node.nags.push(*nag); }
+            },
+            Token::Comment(comment) => match nodes.last_mut() {
+                Some(node) => node.comments.push(comment.clone()),
+                None => pending_comments.push(comment.clone())
+            },
+            Token::VariationStart => {
+                // a variation replaces the move in front of it
+                if nodes.is_empty() { return Err(PgnError::UnbalancedVariation); }
+                let mut variation_comments = Vec::new();
+                let mut alternatives = parse_line(tokens, pos, board_before, true, &mut variation_comments)?;
+                if let Some(first) = alternatives.first_mut() {
+                    variation_comments.append(&mut first.comments);
+                    first.comments = variation_comments;
+                }
+                variations.last_mut().unwrap().append(&mut alternatives);
+            },
+            Token::VariationEnd => {
+                if !nested { return Err(PgnError::UnbalancedVariation); }
+                closed = true;
+                break;
+            },
+            Token::Result(_) | Token::Tag(_, _) => {
+                if nested { return Err(PgnError::UnbalancedVariation); }
+                *pos -= 1;
+                break;
+            }
+        }
+    }
+    if nested && !closed { return Err(PgnError::UnbalancedVariation); }
+    leading_comments.append(&mut pending_comments);
+
+    // hang every move below its predecessor, starting from the end of the line
+    let mut alternatives = Vec::new();
+    for (mut node, mut node_variations) in nodes.into_iter().zip(variations).rev() {
+        node.children = alternatives;
+        alternatives = vec![node];
+        alternatives.append(&mut node_variations);
+    }
+    return Ok(alternatives);
+}
+
+// Parses all games of a PGN file
+pub fn parse_pgn(pgn: &str) -> Result<Vec<Game>, PgnError> {
+    let tokens = tokenize(pgn)?;
+    let mut games = Vec::new();
+    let mut pos = 0;
+
+    while pos < tokens.len() {
+        let mut tags = Vec::new();
+        while let Some(Token::Tag(name, value)) = tokens.get(pos) {
+            tags.push((name.clone(), value.clone()));
+            pos += 1;
+        }
+
+        // games that don't start from the initial position carry it in a FEN tag
+        let fen = tags.iter().find(|(name, _)| name == "FEN").map_or(START_FEN, |(_, value)| value.as_str());
+        let start = board_from_fen(fen).map_err(PgnError::InvalidFen)?;
+
+        let mut comments = Vec::new();
+        let moves = parse_line(&tokens, &mut pos, start, false, &mut comments)?;
+
+        // the result ends the movetext, the Result tag stands in if it is missing
+        let mut result = tags.iter().find(|(name, _)| name == "Result").map_or("*".to_string(), |(_, value)| value.clone());
+        if let Some(Token::Result(movetext_result)) = tokens.get(pos) {
+            result = movetext_result.clone();
+            pos += 1;
+        }
+
+        if tags.is_empty() && moves.is_empty() && comments.is_empty() { continue; }
+        games.push(Game { tags: tags, start: start, comments: comments, moves: moves, result: result });
+    }
+
+    return Ok(games);
+}
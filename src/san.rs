@@ -0,0 +1,168 @@
+use std::fmt;
+
+use crate::engine::{generate_legal_moves, play_move, is_king_checked};
+use crate::models::{FigureType, Board, Move};
+use crate::square::{File, Rank, Square};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SanError {
+    InvalidSyntax(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::InvalidSyntax(san) => write!(f, "'{}' is not a move in standard algebraic notation", san),
+            SanError::IllegalMove(san) => write!(f, "'{}' does not match any legal move", san),
+            SanError::AmbiguousMove(san) => write!(f, "'{}' matches more than one legal move", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+fn piece_letter(figure_type: FigureType) -> Option<char> {
+    match figure_type {
+        FigureType::KING => Some('K'),
+        FigureType::QUEEN => Some('Q'),
+        FigureType::ROOK => Some('R'),
+        FigureType::BISHOP => Some('B'),
+        FigureType::KNIGHT => Some('N'),
+        _ => None
+    }
+}
+
+fn piece_from_letter(c: char) -> Option<FigureType> {
+    match c {
+        'K' => Some(FigureType::KING),
+        'Q' => Some(FigureType::QUEEN),
+        'R' => Some(FigureType::ROOK),
+        'B' => Some(FigureType::BISHOP),
+        'N' => Some(FigureType::KNIGHT),
+        _ => None
+    }
+}
+
+// Castle moves target the rook, so the side is read from the rook's file
+fn castle_san(m: Move) -> &'static str {
+    if m.to.file() > m.from.file() { "O-O" } else { "O-O-O" }
+}
+
+// Formats `m`, a legal move in `board`, in standard algebraic notation
+pub fn move_to_san(m: Move, board: &Board) -> String {
+    let mut san = String::new();
+    let figure_type = board.fields[m.from.index()].figure_type;
+    let legal_moves = generate_legal_moves(board);
+
+    if m.is_castle() {
+        san.push_str(castle_san(m));
+    } else if let Some(letter) = piece_letter(figure_type) {
+        san.push(letter);
+
+        // other pieces of the same type that can reach the target field
+        let rivals: Vec<Square> = legal_moves.iter()
+            .filter(|other| other.to == m.to && other.from != m.from && !other.is_castle())
+            .filter(|other| board.fields[other.from.index()].figure_type == figure_type)
+            .map(|other| other.from)
+            .collect();
+        if !rivals.is_empty() {
+            if rivals.iter().all(|rival| rival.file() != m.from.file()) {
+                san.push(m.from.file().to_char());
+            } else if rivals.iter().all(|rival| rival.rank() != m.from.rank()) {
+                san.push(m.from.rank().to_char());
+            } else {
+                san.push_str(&m.from.to_string());
+            }
+        }
+
+        if m.is_capture() { san.push('x'); }
+        san.push_str(&m.to.to_string());
+    } else {
+        if m.is_capture() {
+            san.push(m.from.file().to_char());
+            san.push('x');
+        }
+        san.push_str(&m.to.to_string());
+        if let Some(letter) = piece_letter(m.promotion) {
+            san.push('=');
+            san.push(letter);
+        }
+    }
+
+    let mut board_cpy = *board;
+    play_move(m, &mut board_cpy);
+    if is_king_checked(board_cpy.active, &board_cpy) {
+        san.push(if generate_legal_moves(&board_cpy).is_empty() { '#' } else { '+' });
+    }
+
+    return san;
+}
+
+// Resolves a move in standard algebraic notation to the legal move of `board` it describes.
+// Check and annotation suffixes are ignored, "0-0" and promotions without '=' are accepted.
+pub fn move_from_san(san: &str, board: &Board) -> Result<Move, SanError> {
+    let invalid = || SanError::InvalidSyntax(san.to_string());
+    let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+    let legal_moves = generate_legal_moves(board);
+    let candidates: Vec<Move> = match text {
+        "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+            let castle = text.replace('0', "O");
+            legal_moves.iter().filter(|m| m.is_castle() && castle_san(**m) == castle).cloned().collect()
+        },
+        _ => {
+            let capture = text.contains('x');
+            let mut chars: Vec<char> = text.chars().filter(|c| *c != 'x').collect();
+
+            // promotion piece, either "e8=Q" or "e8Q"
+            let mut promotion = FigureType::NONE;
+            if let Some(figure_type) = chars.last().and_then(|c| piece_from_letter(*c)) {
+                if figure_type == FigureType::KING { return Err(invalid()); }
+                promotion = figure_type;
+                chars.pop();
+                if chars.last() == Some(&'=') { chars.pop(); }
+            }
+
+            let figure_type = match chars.first().and_then(|c| piece_from_letter(*c)) {
+                Some(figure_type) => { chars.remove(0); figure_type },
+                None => FigureType::PAWN
+            };
+            if figure_type != FigureType::PAWN && promotion != FigureType::NONE { return Err(invalid()); }
+
+            // target field and what is left of the origin
+            if chars.len() < 2 || chars.len() > 4 { return Err(invalid()); }
+            let to = match (File::from_char(chars[chars.len()-2]), Rank::from_char(chars[chars.len()-1])) {
+                (Some(file), Some(rank)) => Square::from_coords(file, rank),
+                _ => return Err(invalid())
+            };
+
+            let mut from_file = None;
+            let mut from_rank = None;
+            for c in chars[..chars.len()-2].iter() {
+                match (File::from_char(*c), Rank::from_char(*c)) {
+                    (Some(file), _) if from_file.is_none() && from_rank.is_none() => from_file = Some(file),
+                    (_, Some(rank)) if from_rank.is_none() => from_rank = Some(rank),
+                    _ => return Err(invalid())
+                }
+            }
+            // pawns capture with their file and an "x", "exd5", and move straight without either
+            if figure_type == FigureType::PAWN && capture != from_file.is_some() { return Err(invalid()); }
+
+            legal_moves.iter()
+                .filter(|m| !m.is_castle() && m.to == to && m.promotion == promotion)
+                .filter(|m| board.fields[m.from.index()].figure_type == figure_type)
+                .filter(|m| figure_type != FigureType::PAWN || (m.from.file() != m.to.file()) == capture)
+                .filter(|m| from_file.is_none() || from_file == Some(m.from.file()))
+                .filter(|m| from_rank.is_none() || from_rank == Some(m.from.rank()))
+                .cloned().collect()
+        }
+    };
+
+    match candidates.len() {
+        0 => Err(SanError::IllegalMove(san.to_string())),
+        1 => Ok(candidates[0]),
+        _ => Err(SanError::AmbiguousMove(san.to_string()))
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Files a to h, `File::A` has index 0
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct File(u8);
+
+// Ranks 1 to 8, `Rank::FIRST` has index 0 and is white's back rank
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Rank(u8);
+
+// One of the 64 fields. The index follows `Board::fields` and the bitboards, so a8 is 0 and h1 is 63.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Square(u8);
+
+impl File {
+    pub const A: File = File(0);
+    pub const B: File = File(1);
+    pub const C: File = File(2);
+    pub const D: File = File(3);
+    pub const E: File = File(4);
+    pub const F: File = File(5);
+    pub const G: File = File(6);
+    pub const H: File = File(7);
+    pub const ALL: [File; 8] = [File::A, File::B, File::C, File::D, File::E, File::F, File::G, File::H];
+
+    pub fn new(index: u8) -> Option<File> {
+        if index < 8 { Some(File(index)) } else { None }
+    }
+
+    pub fn from_char(c: char) -> Option<File> {
+        if ('a'..='h').contains(&c) { Some(File(c as u8 - b'a')) } else { None }
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self.0) as char
+    }
+
+    // The file `files` to the right, towards the h-file
+    pub fn offset(self, files: i8) -> Option<File> {
+        let index = self.0 as i8 + files;
+        if (0..8).contains(&index) { Some(File(index as u8)) } else { None }
+    }
+}
+
+impl Rank {
+    pub const FIRST: Rank = Rank(0);
+    pub const SECOND: Rank = Rank(1);
+    pub const THIRD: Rank = Rank(2);
+    pub const FOURTH: Rank = Rank(3);
+    pub const FIFTH: Rank = Rank(4);
+    pub const SIXTH: Rank = Rank(5);
+    pub const SEVENTH: Rank = Rank(6);
+    pub const EIGHTH: Rank = Rank(7);
+    pub const ALL: [Rank; 8] = [Rank::FIRST, Rank::SECOND, Rank::THIRD, Rank::FOURTH, Rank::FIFTH, Rank::SIXTH, Rank::SEVENTH, Rank::EIGHTH];
+
+    pub fn new(index: u8) -> Option<Rank> {
+        if index < 8 { Some(Rank(index)) } else { None }
+    }
+
+    pub fn from_char(c: char) -> Option<Rank> {
+        if ('1'..='8').contains(&c) { Some(Rank(c as u8 - b'1')) } else { None }
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn to_char(self) -> char {
+        (b'1' + self.0) as char
+    }
+
+    // The rank `ranks` up, towards black's back rank
+    pub fn offset(self, ranks: i8) -> Option<Rank> {
+        let index = self.0 as i8 + ranks;
+        if (0..8).contains(&index) { Some(Rank(index as u8)) } else { None }
+    }
+}
+
+impl Square {
+    // All fields in index order, a8, b8, ... h1
+    pub const ALL: [Square; 64] = {
+        let mut all = [Square(0); 64];
+        let mut index = 0;
+        while index < 64 {
+            all[index] = Square(index as u8);
+            index += 1;
+        }
+        all
+    };
+
+    pub fn new(index: u8) -> Option<Square> {
+        if index < 64 { Some(Square(index)) } else { None }
+    }
+
+    pub fn from_coords(file: File, rank: Rank) -> Square {
+        Square((7 - rank.0) * 8 + file.0)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn file(self) -> File {
+        File(self.0 % 8)
+    }
+
+    pub fn rank(self) -> Rank {
+        Rank(7 - self.0 / 8)
+    }
+
+    // The field `files` to the right and `ranks` up, if it is still on the board
+    pub fn offset(self, files: i8, ranks: i8) -> Option<Square> {
+        Some(Square::from_coords(self.file().offset(files)?, self.rank().offset(ranks)?))
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SquareError(pub String);
+
+impl fmt::Display for SquareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a field, expected a file a-h and a rank 1-8", self.0)
+    }
+}
+
+impl std::error::Error for SquareError {}
+
+// Parses coordinates like "e4"
+impl FromStr for Square {
+    type Err = SquareError;
+
+    fn from_str(s: &str) -> Result<Square, SquareError> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(File::from_char), chars.next().and_then(Rank::from_char), chars.next()) {
+            (Some(file), Some(rank), None) => Ok(Square::from_coords(file, rank)),
+            _ => Err(SquareError(s.to_string()))
+        }
+    }
+}
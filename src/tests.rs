@@ -1,74 +1,765 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::utils::*;
     use crate::models::*;
     use crate::engine::*;
+    use crate::attacks::*;
+    use crate::zobrist::*;
+    use crate::san::*;
+    use crate::pgn::*;
+    use crate::perft::*;
+    use crate::square::*;
     use std::time::Instant;
-    use std::boxed::Box;
+    use std::collections::HashMap;
+
+    fn sq(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
+    fn find_move(board: &Board, src: &str, dst: &str) -> Move {
+        return *calc_legal_moves(sq(src), board).iter().find(|m| m.to == sq(dst)).unwrap();
+    }
 
     #[test]
     fn index_to_position_test() {
-        let position = translate_index_to_position(12);        
+        let position = Square::ALL[12].to_string();
 
         assert_eq!(position, "e7");
     }
 
     #[test]
     fn position_to_index_test() {
-        let index = translate_position_to_index("f1");        
+        let index = sq("f1").index();
 
         assert_eq!(index, 61);
     }
 
     #[test]
     fn translation_test() {
-        for i in 0..64 {
-            let position = translate_index_to_position(i);
-            let index = translate_position_to_index(&position);
+        for square in Square::ALL.iter() {
+            let position = square.to_string();
+            assert_eq!(*square, sq(&position));
+            assert_eq!(*square, Square::from_coords(square.file(), square.rank()));
+        }
+
+        for invalid in ["z9", "i1", "a0", "a9", "e", "e44", ""].iter() {
+            assert_eq!(invalid.parse::<Square>(), Err(SquareError(invalid.to_string())));
+        }
+        assert_eq!(Square::new(64), None);
+    }
+
+    #[test]
+    fn square_offset_test() {
+        assert_eq!(sq("e2").file(), File::E);
+        assert_eq!(sq("e2").rank(), Rank::SECOND);
+        assert_eq!(sq("e2").offset(0, 2), Some(sq("e4")));
+        assert_eq!(sq("g1").offset(-1, 2), Some(sq("f3")));
+        assert_eq!(sq("h4").offset(1, 0), None);
+        assert_eq!(sq("a8").offset(0, 1), None);
+        assert_eq!(File::A.offset(-1), None);
+        assert_eq!(Rank::FIRST.offset(7), Some(Rank::EIGHTH));
+        assert_eq!(File::ALL.iter().map(|file| file.to_char()).collect::<String>(), "abcdefgh");
+        assert_eq!(Rank::ALL.iter().map(|rank| rank.to_string()).collect::<String>(), "12345678");
+    }
+
+    #[test]
+    fn fen_round_trip_test() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "8/8/8/8/8/8/8/4K2k w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        for fen in fens.iter() {
+            let board = board_from_fen(fen).unwrap();
+            assert_eq!(board_to_fen(&board), *fen);
+        }
+    }
+
+    #[test]
+    fn fen_error_test() {
+        assert_eq!(board_from_fen("").unwrap_err(), FenError::MissingField("piece placement"));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k w").unwrap_err(), FenError::MissingField("castling"));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k x - -").unwrap_err(), FenError::InvalidCharacter("active color", 'x'));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k white - -").unwrap_err(), FenError::InvalidField("active color", "white".to_string()));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k w KX -").unwrap_err(), FenError::InvalidCharacter("castling", 'X'));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k w - z9").unwrap_err(), FenError::InvalidCharacter("en passant", 'z'));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k w - e4").unwrap_err(), FenError::InvalidCharacter("en passant", '4'));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2x w - -").unwrap_err(), FenError::InvalidCharacter("piece placement", 'x'));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/4K2k w - -").unwrap_err(), FenError::InvalidRankCount(7));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2kp w - -").unwrap_err(), FenError::InvalidRankLength(1));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2 w - -").unwrap_err(), FenError::InvalidRankLength(1));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K3 w - -").unwrap_err(), FenError::MissingKing(FigureColor::BLACK));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k w - - x 1").unwrap_err(), FenError::InvalidNumber("halfmove clock", "x".to_string()));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k w - - 0 0").unwrap_err(), FenError::InvalidNumber("fullmove number", "0".to_string()));
+        assert_eq!(board_from_fen("8/8/8/8/8/8/8/4K2k w - - 0 1 x").unwrap_err(), FenError::TrailingField("x".to_string()));
+        assert!(board_from_fen("8/8/8/8/8/8/8/4K2k w - -").is_ok());
+    }
+
+    #[test]
+    fn validate_test() {
+        let validate = |fen: &str| board_from_fen(fen).unwrap().validate();
+        assert_eq!(validate(START_FEN), vec![]);
+        assert_eq!(validate("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"), vec![]);
+        assert_eq!(validate("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"), vec![]);
+
+        assert_eq!(validate("4k3/8/8/8/8/8/8/4KK2 w - - 0 1"), vec![PositionError::KingCount(FigureColor::WHITE, 2)]);
+        assert_eq!(validate("P3k3/8/8/8/8/8/8/4K2p w - - 0 1"), vec![PositionError::PawnOnBackRank(sq("a8")), PositionError::PawnOnBackRank(sq("h1"))]);
+        assert_eq!(validate("4k3/8/8/8/8/8/8/4K2R w - - 0 1"), vec![]);
+        assert_eq!(validate("4k3/8/8/8/8/8/8/4R2K w - - 0 1"), vec![PositionError::InactiveKingInCheck(FigureColor::BLACK)]);
+        assert_eq!(validate("4k3/8/8/8/8/8/8/R3K3 w KQ - 0 1"), vec![PositionError::CastleRightWithoutRook('K')]);
+        // the FEN parser rejects rights of a king off its back rank, a board editor doesn't
+        let mut board = board_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        board.set_field(sq("e1"), Field { figure_type: FigureType::NONE, figure_color: FigureColor::NONE });
+        board.set_field(sq("e2"), Field { figure_type: FigureType::KING, figure_color: FigureColor::WHITE });
+        assert_eq!(board.validate(), vec![PositionError::CastleRightWithoutKing('K'), PositionError::CastleRightWithoutKing('Q')]);
+        assert_eq!(validate("4k3/8/8/3p4/8/8/8/4K3 w - d6 0 1"), vec![]);
+        assert_eq!(validate("4k3/8/8/8/3p4/8/8/4K3 w - d6 0 1"), vec![PositionError::InvalidEnPassant(sq("d6"))]);
+        assert_eq!(validate("4k3/8/8/3p4/8/8/8/4K3 b - d6 0 1"), vec![PositionError::InvalidEnPassant(sq("d6"))]);
+        assert_eq!(validate("4k3/3p4/8/3p4/8/8/8/4K3 w - d6 0 1"), vec![PositionError::InvalidEnPassant(sq("d6"))]);
+
+        assert_eq!(PositionError::PawnOnBackRank(sq("h1")).to_string(), "pawn on the back rank at h1");
 
-            assert_eq!(i, index as u8);
+        for case in read_epd(include_str!("../resources/perft.epd")).unwrap().iter() {
+            assert_eq!(case.board.validate(), vec![], "{}", case.fen);
         }
     }
 
-    fn perft_test_rec(board: &mut Board, depth: u8, max_depth: u8) -> usize {
-        if depth == 0 { return 1; }
-        let mut perft_score = 0;
+    #[test]
+    fn board_display_test() {
+        let board = board_from_fen("4k3/8/8/3pP3/8/8/8/R3K2R w KQ d6 0 1").unwrap();
+        let diagram = format!("{}", board);
+        let lines: Vec<&str> = diagram.lines().collect();
+        assert_eq!(lines[0], "  +------------------------+");
+        assert_eq!(lines[1], "8 | .  .  .  .  k  .  .  . |");
+        assert_eq!(lines[4], "5 | .  .  .  p  P  .  .  . |");
+        assert_eq!(lines[8], "1 | R  .  .  .  K  .  .  R |");
+        assert_eq!(lines[10], "    a  b  c  d  e  f  g  h");
+        assert_eq!(lines[12], "fen: 4k3/8/8/3pP3/8/8/8/R3K2R w KQ d6 0 1");
+        assert_eq!(lines[13], format!("white to move, castling KQ, en passant d6, hash {:016x}", board.hash));
+
+        // the fields the pawn on e5 can move to
+        let targets = calc_legal_moves(sq("e5"), &board).iter().fold(0, |marked, m| marked | 1u64 << m.to.index());
+        let diagram = format!("{}", board.marked(targets));
+        assert_eq!(diagram.lines().nth(3).unwrap(), "6 | .  .  . [.][.] .  .  . |");
+
+        assert_eq!(format!("{:#}", board).lines().nth(8).unwrap(), "1 | ♖  ·  ·  ·  ♔  ·  ·  ♖ |");
+    }
+
+    #[test]
+    fn move_flags_test() {
+        let board = board_from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+
+        let castle = find_move(&board, "e1", "h1");
+        assert_eq!(castle.flags, Move::CASTLE);
+        assert_eq!(find_move(&board, "e1", "a1").flags, Move::CASTLE);
+        assert_eq!(find_move(&board, "e5", "d6").flags, Move::CAPTURE | Move::EN_PASSANT);
+        assert_eq!(find_move(&board, "a1", "a8").flags, Move::CAPTURE);
+        assert_eq!(find_move(&board, "e1", "f1").flags, 0);
+
+        let mut board = board;
+        play_move(castle, &mut board);
+        assert_eq!(board_to_fen(&board), "r3k2r/8/8/3pP3/8/8/8/R4RK1 b kq - 1 1");
+
+        let double_push = find_move(&board_from_fen(START_FEN).unwrap(), "e2", "e4");
+        assert_eq!(double_push.flags, Move::DOUBLE_PUSH);
+    }
+
+    #[test]
+    fn move_counters_test() {
+        let mut board = board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+
+        play_move(find_move(&board, "g1", "f3"), &mut board);
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (1, 1));
+        play_move(find_move(&board, "g8", "f6"), &mut board);
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (2, 2));
+        play_move(find_move(&board, "e2", "e4"), &mut board);
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (0, 2));
+        play_move(find_move(&board, "f6", "e4"), &mut board);
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (0, 3));
+
+        assert_eq!(board_to_fen(&board), "rnbqkb1r/pppppppp/8/8/4n3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3");
+
+        // the counters stop at their maximum instead of overflowing
+        let mut board = board_from_fen("4k3/8/8/8/8/8/8/4K3 b - - 65535 65535").unwrap();
+        play_move(find_move(&board, "e8", "d8"), &mut board);
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (65535, 65535));
+    }
+
+    #[test]
+    fn game_status_test() {
+        let board = board_from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert_eq!(game_status(&board, &[]), GameStatus::Checkmate(FigureColor::BLACK));
+
+        let board = board_from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(game_status(&board, &[]), GameStatus::Stalemate);
+
+        let board = board_from_fen("7k/8/6K1/8/8/8/8/R7 b - - 100 80").unwrap();
+        assert_eq!(game_status(&board, &[]), GameStatus::FiftyMoveRule);
+
+        let board = board_from_fen("7k/8/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(game_status(&board, &[]), GameStatus::InsufficientMaterial);
+
+        let mut board = board_from_fen(START_FEN).unwrap();
+        let mut history = vec![position_hash(&board)];
+        assert_eq!(game_status(&board, &history), GameStatus::Ongoing);
+        for _ in 0..2 {
+            for (src, dst) in [("g1", "f3"), ("g8", "f6"), ("f3", "g1"), ("f6", "g8")].iter() {
+                assert_eq!(game_status(&board, &history), GameStatus::Ongoing);
+                play_move(find_move(&board, src, dst), &mut board);
+                history.push(position_hash(&board));
+            }
+        }
+        assert_eq!(game_status(&board, &history), GameStatus::ThreefoldRepetition);
+    }
+
+    #[test]
+    fn insufficient_material_test() {
+        let drawn = [
+            "7k/8/6K1/8/8/8/8/8 w - - 0 1",
+            "7k/8/6K1/8/8/8/8/5B2 w - - 0 1",
+            "7k/8/6K1/8/8/8/8/5n2 w - - 0 1",
+            "7k/8/6K1/8/8/8/b7/5B2 w - - 0 1",
+        ];
+        for fen in drawn.iter() {
+            let board = board_from_fen(fen).unwrap();
+            assert!(board.is_insufficient_material(), "{}", fen);
+            assert_eq!(game_status(&board, &[]), GameStatus::InsufficientMaterial);
+        }
+
+        let playable = [
+            "7k/8/6K1/8/8/8/8/4Bb2 w - - 0 1",
+            "7k/8/6K1/8/8/8/8/4Nn2 w - - 0 1",
+            "7k/8/6K1/8/8/8/8/4BN2 w - - 0 1",
+            "7k/8/6K1/8/8/8/8/4P3 w - - 0 1",
+            "7k/8/6K1/8/8/8/8/4R3 w - - 0 1",
+        ];
+        for fen in playable.iter() {
+            let board = board_from_fen(fen).unwrap();
+            assert!(!board.is_insufficient_material(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn zobrist_test() {
+        // an en passant field without a pawn to capture doesn't change the key
+        let with_en_passant = board_from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        let without_en_passant = board_from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_eq!(with_en_passant.hash, without_en_passant.hash);
+
+        let with_en_passant = board_from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        let without_en_passant = board_from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_ne!(with_en_passant.hash, without_en_passant.hash);
+        assert_eq!(with_en_passant.hash ^ without_en_passant.hash, KEYS[EN_PASSANT_OFFSET + 4]);
+
+        // transpositions share a key
+        let mut board1 = board_from_fen(START_FEN).unwrap();
+        let mut board2 = board1;
+        for (src, dst) in [("g1", "f3"), ("g8", "f6"), ("b1", "c3")].iter() {
+            play_move(find_move(&board1, src, dst), &mut board1);
+        }
+        for (src, dst) in [("b1", "c3"), ("g8", "f6"), ("g1", "f3")].iter() {
+            play_move(find_move(&board2, src, dst), &mut board2);
+        }
+        assert_eq!(board1.hash, board2.hash);
+        assert_eq!(board1.hash, calculate_zobrist(&board1));
+        assert_eq!(board1.hash, board_from_fen(&board_to_fen(&board1)).unwrap().hash);
+    }
+
+    #[test]
+    fn polyglot_key_test() {
+        // reference keys from the Polyglot book format specification
+        let positions: [(&str, u64); 9] = [
+            ("", 0x463b96181691fc9c),
+            ("e2e4", 0x823c9b50fd114196),
+            ("e2e4 d7d5", 0x0756b94461c50fb0),
+            ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+            ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560)
+        ];
+
+        for (moves, key) in positions.iter() {
+            let mut board = board_from_fen(START_FEN).unwrap();
+            for uci in moves.split_whitespace() {
+                play_move(find_move(&board, &uci[0..2], &uci[2..4]), &mut board);
+            }
+            assert_eq!(board.hash, *key, "{}", moves);
+            assert_eq!(calculate_zobrist(&board), *key, "{}", moves);
+        }
+    }
+
+    #[test]
+    fn repetition_search_test() {
+        let board = board_from_fen("k7/r7/q7/8/8/8/8/6NK w - - 10 40").unwrap();
+        let mut repeated_board = board;
+        play_move(find_move(&repeated_board, "g1", "f3"), &mut repeated_board);
+
+        let history = [position_hash(&repeated_board), position_hash(&board)];
+        assert_eq!(repetition_count(&board, &history), 1);
+
+        let mut best_move = None;
+        let score = nega_max_ab(&board, &history, &mut HashMap::new(), 3, &mut best_move);
+        assert_eq!(best_move, Some(find_move(&board, "g1", "f3")));
+        assert_eq!(score, 0);
+
+        // after Nf3 black's only move Ka7 repeats a position of the game, that draw belongs to
+        // this path and must not end up in the table
+        let earlier = board_from_fen("8/k1K5/8/8/8/5N2/8/8 w - - 10 40").unwrap();
+        let mut history = vec![position_hash(&earlier)];
+        let mut board = earlier;
+        for (src, dst) in [("f3", "g1"), ("a7", "a8")].iter() {
+            play_move(find_move(&board, src, dst), &mut board);
+            history.push(position_hash(&board));
+        }
+        let mut transposition_table = HashMap::new();
+        nega_max_ab(&board, &history, &mut transposition_table, 2, &mut None);
+        let mut knight_moved = board;
+        play_move(find_move(&knight_moved, "g1", "f3"), &mut knight_moved);
+        assert!(!transposition_table.contains_key(&position_hash(&knight_moved)));
+    }
+
+    fn assert_bitboards_match(board: &Board) {
         for index in 0..64 {
             let field = board.fields[index];
-            if field.figure_color == board.active {
-                let legal_moves = calc_reachable_fields(index as i8, &board, true);
-                for m in legal_moves {
-                    let mut board_cpy = Box::new(board.clone());
-                    play_move(index as i8, m, &mut board_cpy);
-                    if !is_king_checked(board.active, *board_cpy) {
-                        let score = perft_test_rec(&mut board_cpy, depth-1, max_depth);
-                        if depth == max_depth {
-                            let mut promotion = "";
-                            match m.1 {
-                                FigureType::KNIGHT => promotion = "n",
-                                FigureType::BISHOP => promotion = "b",
-                                FigureType::ROOK => promotion = "r",
-                                FigureType::QUEEN => promotion = "q",
-                                _ => ()
-                            }
-                            println!("{}{}{}: {}", translate_index_to_position(index as u8), translate_index_to_position(m.0 as u8), promotion, score);
-                        }
-                        perft_score += score;
-                    }
+            for figure_type in 0..6 {
+                assert_eq!(board.pieces[figure_type] & (1 << index) != 0, field.figure_type as usize == figure_type);
+            }
+            for figure_color in 0..2 {
+                assert_eq!(board.colors[figure_color] & (1 << index) != 0, field.figure_color as usize == figure_color);
+            }
+        }
+    }
+
+    fn unmake_test_rec(board: &mut Board, depth: u8) {
+        if depth == 0 { return; }
+        for square in Square::ALL.iter().cloned() {
+            if board.fields[square.index()].figure_color != board.active { continue; }
+            for m in calc_legal_moves(square, board) {
+                let fen = board_to_fen(board);
+                let hash = position_hash(board);
+                let undo = play_move(m, board);
+                assert_eq!(board.hash, calculate_zobrist(board));
+                unmake_test_rec(board, depth-1);
+                unmake_move(m, undo, board);
+                assert_bitboards_match(board);
+                assert_eq!(board_to_fen(board), fen);
+                assert_eq!(position_hash(board), hash);
+            }
+        }
+    }
+
+    #[test]
+    fn unmake_move_test() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ];
+        for fen in fens.iter() {
+            let mut board = board_from_fen(fen).unwrap();
+            unmake_test_rec(&mut board, 3);
+        }
+    }
+
+    fn slider_attacks_naive(square: Square, occupied: u64, directions: &[(i8, i8)]) -> u64 {
+        let index = square.index() as i8;
+        let mut attacks = 0;
+        for (dx, dy) in directions.iter() {
+            let (mut x, mut y) = (index % 8 + dx, index / 8 + dy);
+            while (0..8).contains(&x) && (0..8).contains(&y) {
+                attacks |= 1 << (x + y * 8);
+                if occupied & (1 << (x + y * 8)) != 0 { break; }
+                x += dx;
+                y += dy;
+            }
+        }
+        return attacks;
+    }
+
+    #[test]
+    fn slider_attacks_test() {
+        let mut random_state: u64 = 0x2545f4914f6cdd1d;
+        for _ in 0..1000 {
+            random_state ^= random_state << 13;
+            random_state ^= random_state >> 7;
+            random_state ^= random_state << 17;
+            let occupied = random_state & (random_state >> 3);
+            for square in Square::ALL.iter().cloned() {
+                assert_eq!(rook_attacks(square, occupied), slider_attacks_naive(square, occupied, &[(1, 0), (-1, 0), (0, 1), (0, -1)]));
+                assert_eq!(bishop_attacks(square, occupied), slider_attacks_naive(square, occupied, &[(1, 1), (1, -1), (-1, 1), (-1, -1)]));
+            }
+        }
+    }
+
+    #[test]
+    fn square_attacked_test() {
+        let board = board_from_fen("4k3/8/8/3p4/8/1n6/8/R3K2B w - - 0 1").unwrap();
+
+        // white: rook a1 and bishop h1, black: pawn d5 and knight b3
+        assert!(is_square_attacked(sq("a8"), FigureColor::WHITE, &board));
+        assert!(is_square_attacked(sq("d1"), FigureColor::WHITE, &board));
+        assert!(is_square_attacked(sq("f1"), FigureColor::WHITE, &board));
+        assert!(is_square_attacked(sq("d5"), FigureColor::WHITE, &board));
+        assert!(!is_square_attacked(sq("c6"), FigureColor::WHITE, &board));
+        assert!(is_square_attacked(sq("c4"), FigureColor::BLACK, &board));
+        assert!(is_square_attacked(sq("e4"), FigureColor::BLACK, &board));
+        assert!(!is_square_attacked(sq("d3"), FigureColor::BLACK, &board));
+        assert!(is_square_attacked(sq("a1"), FigureColor::BLACK, &board));
+        assert!(is_square_attacked(sq("c1"), FigureColor::BLACK, &board));
+        assert!(!is_square_attacked(sq("e1"), FigureColor::BLACK, &board));
+    }
+
+    #[test]
+    fn attack_map_test() {
+        let board = board_from_fen("3qk3/8/8/3p4/4P3/2N5/8/3RK3 w - - 0 1").unwrap();
+        let map = AttackMap::new(&board);
+
+        // d5 is hit by the e4 pawn, the c3 knight and the d1 rook, and covered by the d8 queen
+        assert_eq!(map.count(sq("d5"), FigureColor::WHITE), 3);
+        assert_eq!(map.lowest_attacker(sq("d5"), FigureColor::WHITE), Some(sq("e4")));
+        assert_eq!(map.attackers(sq("d5"), FigureColor::BLACK), 1u64 << sq("d8").index());
+        assert_eq!(map.lowest_attacker(sq("e4"), FigureColor::BLACK), Some(sq("d5")));
+        assert_eq!(map.lowest_attacker(sq("e4"), FigureColor::WHITE), Some(sq("c3")));
+        assert_eq!(map.count(sq("d4"), FigureColor::WHITE), 1);
+        assert_eq!(map.lowest_attacker(sq("h5"), FigureColor::WHITE), None);
+
+        // the map agrees with the single field queries
+        for case in read_epd(include_str!("../resources/perft.epd")).unwrap().iter() {
+            let map = AttackMap::new(&case.board);
+            for color in [FigureColor::WHITE, FigureColor::BLACK].iter() {
+                for square in Square::ALL.iter().cloned() {
+                    let attacked = map.attacked(*color) & 1u64 << square.index() != 0;
+                    assert_eq!(attacked, is_square_attacked(square, *color, &case.board), "{} {}", case.fen, square);
+                    assert_eq!(attacked, map.lowest_attacker(square, *color).is_some());
                 }
             }
         }
+    }
+
+    #[test]
+    fn move_list_test() {
+        let board = board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+
+        let legal_moves = generate_legal_moves(&board);
+        let captures = generate_captures(&board);
+        let quiet_moves = generate_quiet_moves(&board);
+        assert_eq!(legal_moves.len(), 48);
+        assert_eq!(captures.len(), 8);
+        assert_eq!(quiet_moves.len(), 40);
+        assert!(captures.iter().all(|m| m.is_capture()));
+        assert!(captures.iter().chain(quiet_moves.iter()).all(|m| legal_moves.contains(m)));
+
+        let mut per_field_moves = 0;
+        for square in Square::ALL.iter().cloned() {
+            if board.fields[square.index()].figure_color == board.active {
+                per_field_moves += calc_legal_moves(square, &board).len();
+            }
+        }
+        assert_eq!(per_field_moves, legal_moves.len());
+    }
+
+    #[test]
+    fn pin_and_check_test() {
+        // en passant would expose the king on the rank
+        let board = board_from_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 1").unwrap();
+        assert!(!generate_legal_moves(&board).iter().any(|m| m.is_en_passant()));
+
+        // en passant captures the pawn giving check
+        let board = board_from_fen("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1").unwrap();
+        assert!(generate_legal_moves(&board).iter().any(|m| m.is_en_passant()));
+
+        // in double check only the king moves
+        let board = board_from_fen("4k3/8/8/8/8/5n2/8/Q3K2r w - - 0 1").unwrap();
+        let legal_moves = generate_legal_moves(&board);
+        assert!(!legal_moves.is_empty());
+        assert!(legal_moves.iter().all(|m| m.from == sq("e1")));
+
+        // a single check is blocked, captured or the king steps aside
+        let board = board_from_fen("4k3/8/8/8/8/8/1B6/r3K3 w - - 0 1").unwrap();
+        assert_eq!(generate_legal_moves(&board).len(), 5);
+
+        // pinned pieces only move along the pin
+        let board = board_from_fen("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1").unwrap();
+        assert!(calc_legal_moves(sq("e2"), &board).is_empty());
+        let board = board_from_fen("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1").unwrap();
+        assert_eq!(calc_legal_moves(sq("e2"), &board).len(), 5);
+    }
+
+    #[test]
+    fn san_format_test() {
+        let board = board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(move_to_san(find_move(&board, "e1", "h1"), &board), "O-O");
+        assert_eq!(move_to_san(find_move(&board, "e1", "a1"), &board), "O-O-O");
+        assert_eq!(move_to_san(find_move(&board, "e5", "f7"), &board), "Nxf7");
+        assert_eq!(move_to_san(find_move(&board, "d5", "e6"), &board), "dxe6");
+        assert_eq!(move_to_san(find_move(&board, "a2", "a4"), &board), "a4");
+        assert_eq!(move_to_san(find_move(&board, "c3", "b1"), &board), "Nb1");
+        assert_eq!(move_to_san(find_move(&board, "d2", "h6"), &board), "Bh6");
+
+        // disambiguation by file, by rank and by both
+        let board = board_from_fen("5k2/8/8/8/Q7/8/7K/Q2Q4 w - - 0 1").unwrap();
+        assert_eq!(move_to_san(find_move(&board, "d1", "d4"), &board), "Qdd4");
+        assert_eq!(move_to_san(find_move(&board, "a4", "d4"), &board), "Q4d4");
+        assert_eq!(move_to_san(find_move(&board, "a1", "d4"), &board), "Qa1d4");
+        assert_eq!(move_to_san(find_move(&board, "a4", "b4"), &board), "Qb4+");
+
+        // promotions, check and mate
+        let board = board_from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap();
+        let promotion = calc_legal_moves(sq("g2"), &board).into_iter()
+            .find(|m| m.to == sq("f1") && m.promotion == FigureType::QUEEN).unwrap();
+        assert_eq!(move_to_san(promotion, &board), "gxf1=Q+");
+        let board = board_from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2").unwrap();
+        assert_eq!(move_to_san(find_move(&board, "d8", "h4"), &board), "Qh4#");
+    }
+
+    #[test]
+    fn san_parse_test() {
+        let board = board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for m in generate_legal_moves(&board).iter() {
+            assert_eq!(move_from_san(&move_to_san(*m, &board), &board), Ok(*m));
+        }
+        assert_eq!(move_from_san("0-0", &board), Ok(find_move(&board, "e1", "h1")));
+        assert_eq!(move_from_san("Nxf7!?", &board), Ok(find_move(&board, "e5", "f7")));
+        assert_eq!(move_from_san("Ne5f7", &board), Ok(find_move(&board, "e5", "f7")));
+
+        let board = board_from_fen("r1bqkbnr/pppppppp/2n5/8/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 2 2").unwrap();
+        assert_eq!(move_from_san("Nbd7", &board), Err(SanError::IllegalMove("Nbd7".to_string())));
+        let board = board_from_fen("rnbqkb1r/ppp1pppp/5n2/3p4/3P4/5N2/PPP1PPPP/RNBQKB1R b KQkq - 2 3").unwrap();
+        assert_eq!(move_from_san("Nd7", &board), Err(SanError::AmbiguousMove("Nd7".to_string())));
+        assert_eq!(move_from_san("Nbd7", &board), Ok(find_move(&board, "b8", "d7")));
+        assert_eq!(move_to_san(find_move(&board, "f6", "d7"), &board), "Nfd7");
+        assert_eq!(move_from_san("Nz5", &board), Err(SanError::InvalidSyntax("Nz5".to_string())));
+
+        let board = board_from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N w - - 0 1").unwrap();
+        assert_eq!(move_from_san("b8Q", &board), move_from_san("b8=Q", &board));
+        assert_eq!(move_from_san("bxa8=N", &board).unwrap().promotion, FigureType::KNIGHT);
+        assert_eq!(move_from_san("b8", &board), Err(SanError::IllegalMove("b8".to_string())));
+
+        // a pawn capture has to name the file it comes from
+        let board = board_from_fen("rnbqkbnr/pp1ppppp/8/2p5/3P4/8/PPP1PPPP/RNBQKBNR w KQkq - 0 2").unwrap();
+        assert_eq!(move_from_san("c5", &board), Err(SanError::IllegalMove("c5".to_string())));
+        assert_eq!(move_from_san("xc5", &board), Err(SanError::InvalidSyntax("xc5".to_string())));
+        assert_eq!(move_from_san("dc5", &board), Err(SanError::InvalidSyntax("dc5".to_string())));
+        assert_eq!(move_from_san("dxc5", &board), Ok(find_move(&board, "d4", "c5")));
+    }
+
+    #[test]
+    fn uci_test() {
+        let board = board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let castle = find_move(&board, "e1", "h1");
+        assert_eq!(castle.to_uci(false), "e1g1");
+        assert_eq!(castle.to_uci(true), "e1h1");
+        assert_eq!(find_move(&board, "e1", "a1").to_uci(false), "e1c1");
+        assert_eq!(find_move(&board, "e5", "f7").to_uci(false), "e5f7");
+        assert_eq!(Move::from_uci(&board, "e1g1"), Some(castle));
+        assert_eq!(Move::from_uci(&board, "e1h1"), Some(castle));
+        assert_eq!(Move::from_uci(&board, "e1e3"), None);
+        for m in generate_legal_moves(&board).iter() {
+            assert_eq!(Move::from_uci(&board, &m.to_uci(false)), Some(*m));
+        }
+
+        let board = board_from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap();
+        let promotion = Move::from_uci(&board, "g2h1n").unwrap();
+        assert_eq!(promotion.promotion, FigureType::KNIGHT);
+        assert_eq!(promotion.to_uci(false), "g2h1n");
+    }
+
+    #[test]
+    fn pgn_import_test() {
+        let pgn = r#"[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[Date "1992.11.04"]
+[Round "29"]
+[White "Fischer, Robert J."]
+[Black "Spassky, Boris V."]
+[Result "1/2-1/2"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 {This opening is called the Ruy Lopez.} 3... a6
+4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7
+11. c4 c6 12. cxb5 axb5 13. Nc3 Bb7 14. Bg5 b4 15. Nb1 h6 16. Bh4 c5 17. dxe5
+Nxe4 18. Bxe7 Qxe7 19. exd6 Qf6 20. Nbd2 Nxd6 21. Nc4 Nxc4 22. Bxc4 Nb6
+23. Ne5 Rae8 24. Bxf7+ Rxf7 25. Nxf7 Rxe1+ 26. Qxe1 Kxf7 27. Qe3 Qg5 28. Qxg5
+hxg5 29. b3 Ke6 30. a3 Kd6 31. axb4 cxb4 32. Ra5 Nd5 33. f3 Bc8 34. Kf2 Bf5
+35. Ra7 g6 36. Ra6+ Kc5 37. Ke1 Nf4 38. g3 Nxh3 39. Kd2 Kb5 40. Rd6 Kc5 41. Ra6
+Nf2 42. g4 Bd3 43. Re6 1/2-1/2
+
+[Event "Variations"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"]
+
+{Leading comment} 1. e4 $1 (1. e3 Kd7 (1... Ke7 2. Kd2) 2. Kf2 ; rest of line
+) 1... Kd8!? 2. Kd2 *
+"#;
+        let games = parse_pgn(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let game = &games[0];
+        assert_eq!(game.tag("White"), Some("Fischer, Robert J."));
+        assert_eq!(game.result, "1/2-1/2");
+        assert_eq!(game.mainline().len(), 85);
+        assert_eq!(game.moves[0].children[0].children[0].children[0].children[0].comments, vec!["This opening is called the Ruy Lopez."]);
+        assert_eq!(board_to_fen(game.replay().last().unwrap()), "8/8/4R1p1/2k3p1/1p4P1/1P1b1P2/3K1n2/8 b - - 2 43");
+
+        let game = &games[1];
+        assert_eq!(game.comments, vec!["Leading comment"]);
+        assert_eq!(game.result, "*");
+        assert_eq!(game.moves.len(), 2);
+        let (main, variation) = (&game.moves[0], &game.moves[1]);
+        assert_eq!((main.san.as_str(), &main.nags), ("e4", &vec![1]));
+        assert_eq!(main.children[0].nags, vec![5]);
+        assert_eq!(variation.san, "e3");
+        assert_eq!(variation.children.len(), 2);
+        assert_eq!(variation.children[1].children[0].san, "Kd2");
+        assert_eq!(variation.children[0].children[0].comments, vec!["rest of line"]);
+        assert_eq!(board_to_fen(game.replay().last().unwrap()), "3k4/8/8/8/4P3/8/3K4/8 b - - 2 2");
+
+        assert_eq!(parse_pgn("1. e4 (1. d4").unwrap_err(), PgnError::UnbalancedVariation);
+        assert_eq!(parse_pgn("1. e4 {open").unwrap_err(), PgnError::UnterminatedComment);
+        assert_eq!(parse_pgn("1. e5").unwrap_err(), PgnError::InvalidMove(SanError::IllegalMove("e5".to_string())));
+    }
+
+    #[test]
+    fn pgn_export_test() {
+        let mut game = Game::new(board_from_fen(START_FEN).unwrap());
+        let mut board = game.start;
+        for san in ["f3", "e5", "g4", "Qh4#"].iter() {
+            let m = move_from_san(san, &board).unwrap();
+            game.push_move(m, &board);
+            play_move(m, &mut board);
+        }
+        game.set_result(game_status(&board, &[]));
+        game.set_tag("Date", "2021.05.01");
+        assert_eq!(game.to_pgn(), "[Event \"?\"]\n[Site \"?\"]\n[Date \"2021.05.01\"]\n[Round \"?\"]\n[White \"?\"]\n[Black \"?\"]\n[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n");
+
+        let mut game = Game::new(board_from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap());
+        game.set_tag("White", "Say \"hi\"");
+        let start = game.start;
+        game.push_move(move_from_san("Kd7", &start).unwrap(), &start);
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[White \"Say \\\"hi\\\"\"]\n"));
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]\n"));
+        assert!(pgn.ends_with("\n1... Kd7 *\n"));
+        assert_eq!(parse_pgn(&pgn).unwrap()[0].tag("White"), Some("Say \"hi\""));
+
+        // brackets and escapes inside a tag value
+        game.set_tag("Event", "Open [A] \\ \"B\"]");
+        let pgn = game.to_pgn();
+        assert_eq!(parse_pgn(&pgn).unwrap()[0].tag("Event"), Some("Open [A] \\ \"B\"]"));
+        assert_eq!(parse_pgn("[Event \"a]b\"]\n\n*").unwrap()[0].tag("Event"), Some("a]b"));
+        assert!(matches!(parse_pgn("[Event \"a]\n\n*"), Err(PgnError::InvalidTag(_))));
+
+        // comments, NAGs and variations survive a round trip
+        let pgn = "[Event \"?\"]\n[Site \"?\"]\n[Date \"?\"]\n[Round \"?\"]\n[White \"?\"]\n[Black \"?\"]\n[Result \"*\"]\n\n{Start} 1. e4 $1 (1. d4 d5 (1... Nf6) 2. c4 {Gambit}) 1... e5 2. Nf3 *\n";
+        let game = parse_pgn(pgn).unwrap().remove(0);
+        assert_eq!(game.to_pgn(), pgn);
+
+        // long games are wrapped
+        let pgn = parse_pgn(&"1. Nf3 Nf6 2. Ng1 Ng8 ".repeat(20)).unwrap()[0].to_pgn();
+        assert!(pgn.lines().all(|line| line.len() <= 80));
+        assert_eq!(parse_pgn(&pgn).unwrap()[0].mainline().len(), 80);
+    }
+
+    #[test]
+    fn chess960_test() {
+        assert_eq!(chess960_start_fen(518).unwrap(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1");
+        assert_eq!(chess960_start_fen(0).unwrap(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1");
+        assert_eq!(chess960_start_fen(959).unwrap(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w CAca - 0 1");
+        assert_eq!(chess960_start_fen(960), None);
+        let mut back_ranks = std::collections::HashSet::new();
+        for number in 0..960 {
+            let fen = chess960_start_fen(number).unwrap();
+            let back_rank: Vec<char> = fen[..8].chars().collect();
+            let bishops: Vec<usize> = (0..8).filter(|i| back_rank[*i] == 'b').collect();
+            let king = back_rank.iter().position(|c| *c == 'k').unwrap();
+            let rooks: Vec<usize> = (0..8).filter(|i| back_rank[*i] == 'r').collect();
+            assert_eq!(bishops.len(), 2);
+            assert_ne!(bishops[0] % 2, bishops[1] % 2);
+            assert!(rooks[0] < king && king < rooks[1]);
+            assert!(board_from_fen(&fen).unwrap().chess960);
+            back_ranks.insert(back_rank);
+        }
+        assert_eq!(back_ranks.len(), 960);
+
+        // Shredder-FEN is written back, X-FEN letters resolve to the outermost rooks
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        assert_eq!(board_to_fen(&board_from_fen(fen).unwrap()), fen);
+        assert_eq!(board_to_fen(&board_from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9").unwrap()), fen);
+        assert!(!board_from_fen(START_FEN).unwrap().chess960);
+
+        // each side keeps its own rook files
+        let asymmetric = "1r2k2r/6p1/8/8/8/8/8/R3K1R1 w GAhb - 0 1";
+        let mut board = board_from_fen(asymmetric).unwrap();
+        assert_eq!(board_to_fen(&board), asymmetric);
+        assert_eq!(board_to_fen(&board_from_fen("1r2k2r/6p1/8/8/8/8/8/R3K1R1 w KQkq - 0 1").unwrap()), asymmetric);
+        let castles = |board: &Board| {
+            let mut castles: Vec<String> = generate_legal_moves(board).iter().filter(|m| m.is_castle()).map(|m| m.to_uci(true)).collect();
+            castles.sort();
+            castles
+        };
+        assert_eq!(castles(&board), vec!["e1a1", "e1g1"]);
+        play_move(Move::from_uci(&board, "e1g1").unwrap(), &mut board);
+        assert_eq!(board_to_fen(&board), "1r2k2r/6p1/8/8/8/8/8/R4RK1 b hb - 1 1");
+        let mut board = board_from_fen("1r2k2r/6p1/8/8/8/8/8/R3K1R1 b GAhb - 0 1").unwrap();
+        assert_eq!(castles(&board), vec!["e8b8", "e8h8"]);
+        play_move(Move::from_uci(&board, "b8b7").unwrap(), &mut board);
+        assert_eq!(board_to_fen(&board), "4k2r/1r4p1/8/8/8/8/8/R3K1R1 w GAh - 1 2");
+
+        // the king stays on g1 and only the rook moves
+        let mut board = board_from_fen("4k3/8/8/8/8/8/8/6KR w H - 0 1").unwrap();
+        let castle = Move::from_uci(&board, "g1h1").unwrap();
+        assert!(castle.is_castle());
+        assert_eq!(move_to_san(castle, &board), "O-O");
+        play_move(castle, &mut board);
+        assert_eq!(board_to_fen(&board), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+
+        // the rook on b1 shields c1 from the queen until it moves
+        let board = board_from_fen("4k3/8/8/8/8/8/8/1R3K2 w B - 0 1").unwrap();
+        assert_eq!(move_from_san("O-O-O", &board).unwrap().to_uci(true), "f1b1");
+        let board = board_from_fen("4k3/8/8/8/8/8/8/qR3K2 w B - 0 1").unwrap();
+        assert!(move_from_san("O-O-O", &board).is_err());
+
+        let mut board = board_from_fen(fen).unwrap();
+        let expected = [21, 528, 12189, 326672];
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(perft(&mut board, depth as u8 + 1), *nodes);
+        }
+    }
+
+    #[test]
+    fn perft_divide_test() {
+        let mut board = board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let divide: HashMap<String, u64> = perft_divide(&mut board, 2).iter().map(|(m, count)| (m.to_uci(false), *count)).collect();
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.values().sum::<u64>(), 2039);
+        assert_eq!(divide["e1g1"], 43);
+
+        let reference = read_divide("info string some engine banner\ne1g1: 43\ne1c1: 43\nd5d6: 41\n\nNodes searched: 127\n");
+        assert_eq!(reference.len(), 3);
+        let mut partial = divide.clone();
+        partial.retain(|uci, _| reference.contains_key(uci));
+        assert!(compare_divide(&partial, &reference).is_empty());
 
-        return perft_score;
+        let mut wrong = partial.clone();
+        wrong.insert("d5d6".to_string(), 40);
+        wrong.remove("e1c1");
+        wrong.insert("e1h1".to_string(), 43);
+        assert_eq!(compare_divide(&wrong, &reference), vec!["d5d6: 40, expected 41", "e1c1: missing, expected 43", "e1h1: 43 but not in the reference"]);
     }
 
     #[test]
     fn perft_test_start() {
         let max_depth = 5;
-        let mut board = board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mut board = board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
     
         let start = Instant::now();
-        let perft_score = perft_test_rec(&mut board, max_depth, max_depth);
+        let perft_score = perft(&mut board, max_depth);
         println!("perft test @ depth {} took {}ms", max_depth, start.elapsed().as_millis());
 
         assert_eq!(perft_score, 4865609);
@@ -77,10 +768,10 @@ mod tests {
     #[test]
     fn perft_test2() {
         let max_depth = 4;
-        let mut board = board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let mut board = board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
     
         let start = Instant::now();
-        let perft_score = perft_test_rec(&mut board, max_depth, max_depth);
+        let perft_score = perft(&mut board, max_depth);
         println!("perft test @ depth {} took {}ms", max_depth, start.elapsed().as_millis());
 
         assert_eq!(perft_score, 4085603);
@@ -89,12 +780,44 @@ mod tests {
     #[test]
     fn perft_test_promotion() {
         let max_depth = 5;
-        let mut board = board_from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1");
+        let mut board = board_from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap();
     
         let start = Instant::now();
-        let perft_score = perft_test_rec(&mut board, max_depth, max_depth);
+        let perft_score = perft(&mut board, max_depth);
         println!("perft test @ depth {} took {}ms", max_depth, start.elapsed().as_millis());
 
         assert_eq!(perft_score, 3605103);
     }
+
+    // The suite runs up to depth 4 by default, PERFT_DEPTH=<depth> checks deeper counts
+    #[test]
+    fn perft_suite_test() {
+        let max_depth = std::env::var("PERFT_DEPTH").ok().and_then(|depth| depth.parse().ok()).unwrap_or(4);
+        let cases = read_epd(include_str!("../resources/perft.epd")).unwrap();
+
+        let start = Instant::now();
+        let failures = run_suite(&cases, max_depth, |case, failures| {
+            if !failures.is_empty() { println!("{}\n", case.board); }
+        });
+        println!("perft suite @ depth {} took {}ms", max_depth, start.elapsed().as_millis());
+
+        let report: Vec<String> = failures.iter().map(|failure| failure.to_string()).collect();
+        assert!(failures.is_empty(), "{}", report.join("\n"));
+    }
+
+    #[test]
+    fn epd_test() {
+        let cases = read_epd("8/8/8/8/8/8/8/4K2k w - - ;D1 5 ;D2 15\n\n8/8/8/8/8/8/8/4K2k b - - 0 1 ;D1 3\n").unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].counts, vec![(1, 5), (2, 15)]);
+
+        let failures = run_suite(&cases, 1, |_, _| ());
+        assert_eq!(failures.len(), 0);
+        let cases = read_epd("8/8/8/8/8/8/8/4K2k w - - ;D1 4").unwrap();
+        let failures = run_suite(&cases, 1, |_, _| ());
+        assert_eq!(failures[0].to_string(), "8/8/8/8/8/8/8/4K2k w - - depth 1: 5 nodes, expected 4");
+
+        assert_eq!(read_epd("8/8/8/8/8/8/8/4K2k w - - ;D1").err(), Some(EpdError::InvalidCount(1, "D1".to_string())));
+        assert_eq!(read_epd("\n8/8/8/8/8/8/8/4K3 w - - ;D1 5").err(), Some(EpdError::InvalidFen(2, FenError::MissingKing(FigureColor::BLACK))));
+    }
 }
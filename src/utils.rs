@@ -1,81 +1,267 @@
+use std::fmt;
+
 use crate::models::{FigureType, FigureColor, Field, Board, CastleRights};
+use crate::zobrist::calculate_zobrist;
+use crate::square::{File, Rank, Square};
 
-pub fn translate_position_to_index(pos: &str) -> i8 {
-    let pos_chars: Vec<char> = pos.chars().collect();
-    if pos_chars.len() == 2 {
-        let file = (pos_chars[0] as i8) - 97;
-        let rank = 7 - ((pos_chars[1] as i8) - 49);
-        return file + rank * 8;
-    }
-    return -1;
-}
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
-#[allow(dead_code)]
-pub fn translate_index_to_position(index: u8) -> String {
-    let mut file = index % 8;
-    let mut rank = (index-file) / 8;
+#[derive(Clone, Debug, PartialEq)]
+pub enum FenError {
+    MissingField(&'static str),
+    InvalidCharacter(&'static str, char),
+    InvalidRankLength(u8),
+    InvalidRankCount(usize),
+    MissingKing(FigureColor),
+    InvalidNumber(&'static str, String),
+    InvalidField(&'static str, String),
+    TrailingField(String),
+}
 
-    file += 97;
-    rank = (7 - rank) + 49;
-    return format!("{}{}", file as char, rank as char);
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {} field", field),
+            FenError::InvalidCharacter(field, c) => write!(f, "invalid character '{}' in {} field", c, field),
+            FenError::InvalidRankLength(rank) => write!(f, "rank {} does not describe exactly 8 squares", rank),
+            FenError::InvalidRankCount(count) => write!(f, "expected 8 ranks in piece placement, found {}", count),
+            FenError::MissingKing(color) => write!(f, "no {} king on the board", if *color == FigureColor::WHITE { "white" } else { "black" }),
+            FenError::InvalidNumber(field, value) => write!(f, "invalid number '{}' in {} field", value, field),
+            FenError::InvalidField(field, value) => write!(f, "invalid {} field '{}'", field, value),
+            FenError::TrailingField(field) => write!(f, "unexpected field '{}' after the fullmove number", field),
+        }
+    }
 }
 
-pub fn board_from_fen(fen: &str) -> Board {
-    let fen_split: Vec<&str> = fen.split(' ').collect();
+impl std::error::Error for FenError {}
 
-    let fen_board = fen_split[0];
-    let fen_active = fen_split[1];
-    let fen_castle = fen_split[2];
-    let fen_en_passant = fen_split[3];
+pub fn board_from_fen(fen: &str) -> Result<Board, FenError> {
+    let mut fen_split = fen.split_whitespace();
 
-    // active color
-    let mut active = FigureColor::NONE;
-    match fen_active {
-        "w" => active = FigureColor::WHITE,
-        "b" => active = FigureColor::BLACK,
-        _ => ()
-    }
+    let fen_board = fen_split.next().ok_or(FenError::MissingField("piece placement"))?;
+    let fen_active = fen_split.next().ok_or(FenError::MissingField("active color"))?;
+    let fen_castle = fen_split.next().ok_or(FenError::MissingField("castling"))?;
+    let fen_en_passant = fen_split.next().ok_or(FenError::MissingField("en passant"))?;
+    // the move counters are optional, EPD records omit them
+    let fen_halfmove_clock = fen_split.next().unwrap_or("0");
+    let fen_fullmove_number = fen_split.next().unwrap_or("1");
+    if let Some(field) = fen_split.next() { return Err(FenError::TrailingField(field.to_string())); }
 
-    // castle rights
-    let mut castle_rights = CastleRights { K: false, Q: false, k: false, q: false};
-    if fen_castle.contains("K") { castle_rights.K = true; }
-    if fen_castle.contains("Q") { castle_rights.Q = true; }
-    if fen_castle.contains("k") { castle_rights.k = true; }
-    if fen_castle.contains("q") { castle_rights.q = true; }
+    // active color
+    let active = match fen_active {
+        "w" => FigureColor::WHITE,
+        "b" => FigureColor::BLACK,
+        _ if fen_active.chars().count() > 1 => return Err(FenError::InvalidField("active color", fen_active.to_string())),
+        _ => return Err(FenError::InvalidCharacter("active color", fen_active.chars().next().unwrap()))
+    };
 
     // en passant
+    let mut en_passant = None;
+    if fen_en_passant != "-" {
+        let mut en_passant_chars = fen_en_passant.chars();
+        let file_char = en_passant_chars.next().unwrap();
+        let file = File::from_char(file_char).ok_or(FenError::InvalidCharacter("en passant", file_char))?;
+        let rank_char = en_passant_chars.next().ok_or(FenError::MissingField("en passant rank"))?;
+        let rank = match rank_char { '3' => Rank::THIRD, '6' => Rank::SIXTH, _ => return Err(FenError::InvalidCharacter("en passant", rank_char)) };
+        if let Some(c) = en_passant_chars.next() { return Err(FenError::InvalidCharacter("en passant", c)); }
+        en_passant = Some(Square::from_coords(file, rank));
+    }
+
+    // move counters
+    let halfmove_clock = fen_halfmove_clock.parse().map_err(|_| FenError::InvalidNumber("halfmove clock", fen_halfmove_clock.to_string()))?;
+    // move numbers start at 1
+    let fullmove_number = match fen_fullmove_number.parse() {
+        Ok(0) | Err(_) => return Err(FenError::InvalidNumber("fullmove number", fen_fullmove_number.to_string())),
+        Ok(number) => number
+    };
 
-    let mut i = 0;
     let mut board: Board = Board {
         fields: [Field {figure_type: FigureType::NONE, figure_color: FigureColor::NONE}; 64],
+        pieces: [0; 6],
+        colors: [0; 2],
         active: active,
-        castle_rights: castle_rights,
-        en_passant: translate_position_to_index(fen_en_passant)
+        castle_rights: CastleRights { K: false, Q: false, k: false, q: false, king_side_file: [File::H; 2], queen_side_file: [File::A; 2] },
+        en_passant: en_passant,
+        halfmove_clock: halfmove_clock,
+        fullmove_number: fullmove_number,
+        hash: 0,
+        chess960: false
     };
 
-    for c in fen_board.chars() {
-        if i > 63 { break; }
-        let color = if c.is_lowercase() { FigureColor::BLACK } else { FigureColor::WHITE };
-        if c.is_digit(10) {
-            let offset = c.to_digit(10);
-            match offset {
-                Some(x) => i += x,
-                _ => ()
+    // piece placement
+    let ranks: Vec<&str> = fen_board.split('/').collect();
+    if ranks.len() != 8 { return Err(FenError::InvalidRankCount(ranks.len())); }
+
+    for (rank_index, rank) in ranks.iter().enumerate() {
+        let mut file: usize = 0;
+        for c in rank.chars() {
+            if let Some(offset) = c.to_digit(10) {
+                if offset == 0 || offset > 8 { return Err(FenError::InvalidCharacter("piece placement", c)); }
+                file += offset as usize;
+                continue;
             }
-        } else if c.is_ascii_alphanumeric() { 
-            let figure: Vec<_> = c.to_lowercase().collect();
-            match figure[0] {
-                'k' => board.fields[i as usize] = Field { figure_type: FigureType::KING, figure_color: color },
-                'q' => board.fields[i as usize] = Field { figure_type: FigureType::QUEEN, figure_color: color },
-                'r' => board.fields[i as usize] = Field { figure_type: FigureType::ROOK, figure_color: color },
-                'n' => board.fields[i as usize] = Field { figure_type: FigureType::KNIGHT, figure_color: color },
-                'b' => board.fields[i as usize] = Field { figure_type: FigureType::BISHOP, figure_color: color },
-                'p' => board.fields[i as usize] = Field { figure_type: FigureType::PAWN, figure_color: color },
-                _ => ()
+
+            if file > 7 { return Err(FenError::InvalidRankLength(8 - rank_index as u8)); }
+            let color = if c.is_lowercase() { FigureColor::BLACK } else { FigureColor::WHITE };
+            let figure_type = match c.to_ascii_lowercase() {
+                'k' => FigureType::KING,
+                'q' => FigureType::QUEEN,
+                'r' => FigureType::ROOK,
+                'n' => FigureType::KNIGHT,
+                'b' => FigureType::BISHOP,
+                'p' => FigureType::PAWN,
+                _ => return Err(FenError::InvalidCharacter("piece placement", c))
+            };
+            board.set_field(Square::from_coords(File::ALL[file], Rank::ALL[7 - rank_index]), Field { figure_type: figure_type, figure_color: color });
+            file += 1;
+        }
+        if file != 8 { return Err(FenError::InvalidRankLength(8 - rank_index as u8)); }
+    }
+
+    for color in [FigureColor::WHITE, FigureColor::BLACK].iter() {
+        if board.pieces_of(FigureType::KING, *color) == 0 { return Err(FenError::MissingKing(*color)); }
+    }
+
+    // castle rights, the rooks are looked up once the pieces are placed
+    if fen_castle != "-" {
+        for c in fen_castle.chars() {
+            parse_castle_right(c, &mut board)?;
+        }
+    }
+
+    board.hash = calculate_zobrist(&board);
+
+    return Ok(board);
+}
+
+// Reads one character of a castling field: KQkq as in standard FEN and X-FEN, where they name the
+// outermost rook on that side of the king, or the rook's file as in Shredder-FEN.
+// Any castling rook or king off its standard field makes the board a Chess960 board.
+fn parse_castle_right(c: char, board: &mut Board) -> Result<(), FenError> {
+    let color = if c.is_lowercase() { FigureColor::BLACK } else { FigureColor::WHITE };
+    let back_rank = if color == FigureColor::BLACK { Rank::EIGHTH } else { Rank::FIRST };
+    let king_file = match board.king_square(color) {
+        Some(king) if king.rank() == back_rank => king.file(),
+        _ => return Err(FenError::InvalidCharacter("castling", c))
+    };
+    let rook_files: Vec<File> = File::ALL.iter().cloned().filter(|file| {
+        let field = board.fields[Square::from_coords(*file, back_rank).index()];
+        field.figure_type == FigureType::ROOK && field.figure_color == color
+    }).collect();
+
+    // like in standard FEN, rights without a matching rook are kept, they just never allow castling
+    let (rook_file, king_side) = match c.to_ascii_lowercase() {
+        'k' => (rook_files.iter().rev().find(|file| **file > king_file).cloned(), true),
+        'q' => (rook_files.iter().find(|file| **file < king_file).cloned(), false),
+        'a'..='h' => {
+            board.chess960 = true;
+            let file = File::from_char(c.to_ascii_lowercase()).unwrap();
+            if file == king_file { return Err(FenError::InvalidCharacter("castling", c)); }
+            (Some(file), file > king_file)
+        },
+        _ => return Err(FenError::InvalidCharacter("castling", c))
+    };
+
+    let rights = &mut board.castle_rights;
+    match (color, king_side) {
+        (FigureColor::WHITE, true) => rights.K = true,
+        (FigureColor::WHITE, false) => rights.Q = true,
+        (_, true) => rights.k = true,
+        (_, false) => rights.q = true
+    }
+    if let Some(rook_file) = rook_file {
+        if king_side { rights.king_side_file[color as usize] = rook_file; } else { rights.queen_side_file[color as usize] = rook_file; }
+        if king_file != File::E || rook_file != if king_side { File::H } else { File::A } { board.chess960 = true; }
+    }
+    return Ok(());
+}
+
+pub fn board_to_fen(board: &Board) -> String {
+    let mut fen = String::new();
+
+    // piece placement
+    for rank in Rank::ALL.iter().rev() {
+        let mut empty = 0;
+        for file in File::ALL.iter() {
+            let field = board.fields[Square::from_coords(*file, *rank).index()];
+            let c = match field.figure_type {
+                FigureType::KING => 'k',
+                FigureType::QUEEN => 'q',
+                FigureType::ROOK => 'r',
+                FigureType::KNIGHT => 'n',
+                FigureType::BISHOP => 'b',
+                FigureType::PAWN => 'p',
+                FigureType::NONE => { empty += 1; continue; }
+            };
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+                empty = 0;
             }
-            i += 1;
+            fen.push(if field.figure_color == FigureColor::WHITE { c.to_ascii_uppercase() } else { c });
         }
+        if empty > 0 { fen.push_str(&empty.to_string()); }
+        if *rank != Rank::FIRST { fen.push('/'); }
     }
 
-    return board;
+    // active color
+    fen.push_str(if board.active == FigureColor::BLACK { " b " } else { " w " });
+
+    // castle rights, Chess960 boards name the rook files as in Shredder-FEN
+    let castle_rights = board.castle_rights;
+    let (mut king_side, mut queen_side) = (['k'; 2], ['q'; 2]);
+    if board.chess960 {
+        king_side = castle_rights.king_side_file.map(|file| file.to_char());
+        queen_side = castle_rights.queen_side_file.map(|file| file.to_char());
+    }
+    let (black, white) = (FigureColor::BLACK as usize, FigureColor::WHITE as usize);
+    if castle_rights.K { fen.push(king_side[white].to_ascii_uppercase()); }
+    if castle_rights.Q { fen.push(queen_side[white].to_ascii_uppercase()); }
+    if castle_rights.k { fen.push(king_side[black]); }
+    if castle_rights.q { fen.push(queen_side[black]); }
+    if !(castle_rights.K || castle_rights.Q || castle_rights.k || castle_rights.q) { fen.push('-'); }
+
+    // en passant
+    match board.en_passant {
+        Some(en_passant) => fen.push_str(&format!(" {}", en_passant)),
+        None => fen.push_str(" -")
+    }
+
+    // move counters
+    fen.push_str(&format!(" {} {}", board.halfmove_clock, board.fullmove_number));
+
+    return fen;
+}
+
+// FEN of the Chess960 start position with the given Scharnagl number, 518 is the standard position
+pub fn chess960_start_fen(number: u16) -> Option<String> {
+    if number >= 960 { return None; }
+
+    let mut rank = [' '; 8];
+    let mut n = number as usize;
+    // bishops on a light and a dark field
+    rank[2 * (n % 4) + 1] = 'b';
+    n /= 4;
+    rank[2 * (n % 4)] = 'b';
+    n /= 4;
+
+    // queen and knights go on the n-th empty field
+    fn place(rank: &mut [char; 8], piece: char, n: usize) {
+        let index = (0..8).filter(|i| rank[*i] == ' ').nth(n).unwrap();
+        rank[index] = piece;
+    }
+    place(&mut rank, 'q', n % 6);
+    n /= 6;
+    let knights = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)][n];
+    place(&mut rank, 'n', knights.1);
+    place(&mut rank, 'n', knights.0);
+
+    // the king between the rooks on the last three fields
+    place(&mut rank, 'r', 0);
+    place(&mut rank, 'k', 0);
+    place(&mut rank, 'r', 0);
+
+    let black: String = rank.iter().collect();
+    let rook_files: Vec<char> = File::ALL.iter().filter(|file| rank[file.index()] == 'r').map(|file| file.to_char()).collect();
+    let castling = format!("{}{}{}{}", rook_files[1].to_ascii_uppercase(), rook_files[0].to_ascii_uppercase(), rook_files[1], rook_files[0]);
+    return Some(format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {} - 0 1", black, black.to_uppercase(), castling));
 }